@@ -60,7 +60,7 @@ impl Capsule {
     #[wasm_bindgen(catch, method)]
     pub fn eval(&self, code: &str) -> Result<(), JsValue> {
         let mut cap = self.capsule.borrow_mut();
-        cap.eval(code).map_err(|e| e.to_string())?;
+        cap.eval(code).map_err(|e| e.report(code).to_string())?;
         Ok(())
     }
 }
@@ -1,11 +1,81 @@
 use std::env;
+use std::fs;
+use std::process;
 
-use urashima::{Fallible, Runtime};
+use urashima::Runtime;
 
-fn main() -> Fallible<()> {
+fn main() {
     env_logger::init();
-    let path = env::args().nth(1).unwrap();
+    let args: Vec<String> = env::args().skip(1).collect();
     let rt = Runtime::new();
-    rt.execute(path)?;
-    Ok(())
+    match args.first().map(String::as_str) {
+        Some("test") => test(&rt, &args[1..]),
+        Some(path) => run(&rt, path),
+        None => usage(),
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("usage: naru <path>\n       naru test <path> [filter]");
+    process::exit(2);
+}
+
+fn run(rt: &Runtime, path: &str) {
+    if let Err(e) = rt.execute(path) {
+        let source = fs::read_to_string(path).unwrap_or_default();
+        eprint!("{}", e.report(&source));
+        process::exit(1);
+    }
+}
+
+/// Run the tests of the package, and exit with a non-zero status if any of them fails.
+fn test(rt: &Runtime, args: &[String]) {
+    let path = match args.first() {
+        Some(path) => path,
+        None => usage(),
+    };
+    let source = fs::read_to_string(path).unwrap_or_default();
+    let runner = match args.get(1) {
+        Some(filter) => rt.test_runner().filter(filter.as_str()),
+        None => rt.test_runner(),
+    };
+    let report = match runner.run(path) {
+        Ok(report) => report,
+        Err(e) => {
+            eprint!("{}", e.report(&source));
+            process::exit(1);
+        }
+    };
+
+    println!("running {} tests", report.results.len());
+    for result in &report.results {
+        let status = if result.passed() { "ok" } else { "FAILED" };
+        println!(
+            "test {} ... {} ({:.2?})",
+            result.name, status, result.duration
+        );
+    }
+    let failures: Vec<_> = report.results.iter().filter(|r| !r.passed()).collect();
+    if !failures.is_empty() {
+        println!("\nfailures:\n");
+        for result in &failures {
+            println!("---- {} ----", result.name);
+            print!("{}", result.stdout);
+            if let Some(e) = &result.error {
+                print!("{}", e.report(&source));
+            }
+            println!();
+        }
+    }
+    println!(
+        "test result: {}. {} passed; {} failed; {} filtered out; finished in {:.2?}",
+        if report.is_success() { "ok" } else { "FAILED" },
+        report.passed(),
+        report.failed(),
+        report.filtered_out,
+        report.duration(),
+    );
+    if !report.is_success() {
+        process::exit(1);
+    }
 }
@@ -8,19 +8,35 @@ use urashima_util::PackagePath;
 
 use crate::{
     data::Variant,
-    environment::{Environment, Package},
-    error::{Error, Fallible},
+    environment::{Environment, Package, Scope},
+    error::{Error, Fallible, Frame},
     eval::Evaluate,
     runtime::RuntimeContextRef,
 };
 
 pub use self::builder::CapsuleBuilder;
 
+/// How a capsule executes its programs
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Engine {
+    /// Evaluate the syntax tree directly
+    #[default]
+    TreeWalking,
+    /// Translate into instructions and run them on the virtual machine
+    Bytecode,
+}
+
+/// Number of values and functions allocated before the garbage is collected automatically
+pub const DEFAULT_GC_THRESHOLD: usize = 4096;
+
 pub struct Capsule<'a> {
     pub(crate) ctx: RuntimeContextRef,
     pub(crate) environment: Environment,
     pub(crate) expr_arena: ExprArena,
     pub(crate) stdout: Box<dyn Write + Send + 'a>,
+    pub(crate) engine: Engine,
+    gc_threshold: Option<usize>,
+    next_gc: usize,
 }
 
 impl Capsule<'static> {
@@ -36,6 +52,9 @@ impl<'a> Capsule<'a> {
             environment: Default::default(),
             expr_arena: ExprArena::new(),
             stdout,
+            engine: Engine::default(),
+            gc_threshold: Some(DEFAULT_GC_THRESHOLD),
+            next_gc: DEFAULT_GC_THRESHOLD,
         }
     }
 
@@ -53,6 +72,28 @@ impl<'a> Capsule<'a> {
         code.eval(self)
     }
 
+    /// Free the values and the functions which are no longer reachable from the bindings,
+    /// and returns how many are freed. Values taken out of the capsule may refer to
+    /// freed ones afterwards.
+    pub fn collect_garbage(&mut self) -> usize {
+        let freed = self.environment.collect_garbage();
+        let live = self.environment.allocated();
+        self.next_gc = self.gc_threshold.unwrap_or(0).max(live * 2);
+        log::debug!("collect_garbage: {} freed, {} live", freed, live);
+        freed
+    }
+
+    /// Collect the garbage if the arenas have grown past the threshold. This is called between
+    /// top-level statements, where no values are held outside of the bindings.
+    pub(crate) fn safe_point(&mut self) {
+        if self.gc_threshold.is_some()
+            && self.environment.allocated() >= self.next_gc
+            && self.environment.is_top_level()
+        {
+            self.collect_garbage();
+        }
+    }
+
     pub(crate) fn load(&mut self, path: PackagePath) -> Fallible<Arc<Package>> {
         let mut res = Err(Error::import(&path));
         let ctx = Arc::clone(&self.ctx);
@@ -62,10 +103,13 @@ impl<'a> Capsule<'a> {
                 return entry;
             }
             res = (|| {
-                let input = self::internal::load(&self.ctx.paths, &path)?;
+                let (file, input) = self::internal::load(&self.ctx.paths, &path)?;
                 let mut pkg_capsule = Capsule::root(self.ctx.clone());
-                let prog: PackageProgram = pkg_capsule.parse_sourcecode(&input)?;
-                prog.eval(&mut pkg_capsule)?;
+                pkg_capsule.environment.set_path(&file);
+                pkg_capsule
+                    .parse_sourcecode(&input)
+                    .and_then(|prog: PackageProgram| prog.eval(&mut pkg_capsule))
+                    .map_err(|e| e.with_path(&file))?;
                 let pkg = Package {
                     environment: pkg_capsule.environment,
                 };
@@ -83,10 +127,26 @@ impl<'a> Capsule<'a> {
         ContextGuard::new(self)
     }
 
+    pub(crate) fn enter(&mut self, scope: &Arc<Scope>) -> ScopeGuard<'_, 'a> {
+        ScopeGuard::new(self, scope)
+    }
+
     pub(crate) fn bind(&mut self, name: &str, value: Variant) {
         self.environment.bind(name, value);
     }
 
+    /// Run the call in a new frame, so that errors raised inside it carry the call stack.
+    pub(crate) fn call_frame<T>(
+        &mut self,
+        frame: Frame,
+        call: impl FnOnce(&mut Self) -> Fallible<T>,
+    ) -> Fallible<T> {
+        self.environment.push_frame(frame);
+        let result = call(self).map_err(|e| e.with_frames(self.environment.frames()));
+        self.environment.pop_frame();
+        result
+    }
+
     pub(crate) fn print(&mut self, args: fmt::Arguments<'_>) -> Fallible<()> {
         write!(&mut self.stdout, "{}", args).expect("write error");
         Ok(())
@@ -122,17 +182,55 @@ impl DerefMut for ContextGuard<'_, '_> {
     }
 }
 
+pub(crate) struct ScopeGuard<'a, 'b> {
+    ctx: &'a mut Capsule<'b>,
+}
+
+impl<'a, 'b> ScopeGuard<'a, 'b> {
+    fn new(ctx: &'a mut Capsule<'b>, scope: &Arc<Scope>) -> Self {
+        ctx.environment.enter(scope);
+        ScopeGuard { ctx }
+    }
+}
+
+impl Drop for ScopeGuard<'_, '_> {
+    fn drop(&mut self) {
+        self.ctx.environment.leave();
+    }
+}
+
+impl<'b> Deref for ScopeGuard<'_, 'b> {
+    type Target = Capsule<'b>;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.ctx
+    }
+}
+
+impl DerefMut for ScopeGuard<'_, '_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut *self.ctx
+    }
+}
+
 mod builder {
     use super::*;
 
     pub struct CapsuleBuilder<'a> {
         ctx: RuntimeContextRef,
         stdout: Option<Box<dyn Write + Send + 'a>>,
+        engine: Engine,
+        gc_threshold: Option<usize>,
     }
 
     impl<'a> CapsuleBuilder<'a> {
         pub(crate) fn new(ctx: RuntimeContextRef) -> Self {
-            CapsuleBuilder { ctx, stdout: None }
+            CapsuleBuilder {
+                ctx,
+                stdout: None,
+                engine: Engine::default(),
+                gc_threshold: Some(DEFAULT_GC_THRESHOLD),
+            }
         }
 
         pub fn stdout(mut self, w: Box<dyn Write + Send + 'a>) -> Self {
@@ -140,11 +238,27 @@ mod builder {
             self
         }
 
+        pub fn engine(mut self, engine: Engine) -> Self {
+            self.engine = engine;
+            self
+        }
+
+        /// Number of values and functions allocated before the garbage is collected
+        /// automatically. `None` disables the automatic collection.
+        pub fn gc_threshold(mut self, threshold: Option<usize>) -> Self {
+            self.gc_threshold = threshold;
+            self
+        }
+
         pub fn build(self) -> Capsule<'a> {
-            Capsule::new(
+            let mut capsule = Capsule::new(
                 self.ctx,
                 self.stdout.unwrap_or_else(|| Box::new(std::io::stdout())),
-            )
+            );
+            capsule.engine = self.engine;
+            capsule.gc_threshold = self.gc_threshold;
+            capsule.next_gc = self.gc_threshold.unwrap_or(0);
+            capsule
         }
     }
 }
@@ -174,14 +288,16 @@ mod internal {
 
     use crate::error::{Error, Fallible};
 
-    pub(super) fn load(paths: &[PathBuf], pkg_path: &PackagePath) -> Fallible<String> {
+    /// Find the source file of the package, and returns its path with the content.
+    pub(super) fn load(paths: &[PathBuf], pkg_path: &PackagePath) -> Fallible<(PathBuf, String)> {
         for base_path in paths {
             let mut path = base_path.clone();
             path.extend(pkg_path.into_iter().map(|i| i.as_ref()));
             path.set_extension("n");
             log::info!("{}", path.display());
             if path.is_file() {
-                return Ok(from_path(&path)?);
+                let input = from_path(&path)?;
+                return Ok((path, input));
             }
         }
         Err(Error::import(pkg_path))
@@ -193,3 +309,65 @@ mod internal {
         Ok(input)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::io;
+
+    use super::*;
+    use crate::runtime::Runtime;
+
+    #[test]
+    fn collect_garbage() {
+        for &engine in &[Engine::TreeWalking, Engine::Bytecode] {
+            let rt = Runtime::new();
+            let mut out = Vec::new();
+            {
+                let mut capsule = rt
+                    .capsule_builder()
+                    .stdout(Box::new(io::Cursor::new(&mut out)))
+                    .engine(engine)
+                    .gc_threshold(None)
+                    .build();
+                capsule
+                    .eval("mk := fn { h := fn { 'hi' }; fn { h() } }\nmk()\nmk()\nk := mk()")
+                    .unwrap();
+                assert_eq!(capsule.environment.allocated(), 7);
+                assert_eq!(capsule.collect_garbage(), 4);
+                assert_eq!(capsule.environment.allocated(), 3);
+                capsule.eval("k() println()").unwrap();
+            }
+            assert_eq!(std::str::from_utf8(&out).unwrap(), "hi\n", "{:?}", engine);
+        }
+    }
+
+    #[test]
+    fn collect_garbage_through_vec() {
+        let rt = Runtime::new();
+        let mut out = Vec::new();
+        {
+            let mut capsule = rt
+                .capsule_builder()
+                .stdout(Box::new(io::Cursor::new(&mut out)))
+                .gc_threshold(None)
+                .build();
+            capsule.eval("v := [new 1, new 2]\nnew 3").unwrap();
+            assert_eq!(capsule.environment.allocated(), 3);
+            assert_eq!(capsule.collect_garbage(), 1);
+            capsule.eval("v[1] get() println()").unwrap();
+        }
+        assert_eq!(std::str::from_utf8(&out).unwrap(), "2\n");
+    }
+
+    #[test]
+    fn collect_garbage_automatically() {
+        let rt = Runtime::new();
+        let mut capsule = rt.capsule_builder().gc_threshold(Some(8)).build();
+        capsule.eval("f := fn { fn { 1 } }").unwrap();
+        for _ in 0..100 {
+            capsule.eval("f()").unwrap();
+        }
+        assert!(capsule.environment.allocated() <= 8);
+        capsule.eval("f()()").unwrap();
+    }
+}
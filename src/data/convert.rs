@@ -1,45 +1,69 @@
-use super::{symbol, Int, Nat, Variant};
+use super::{symbol, Int, Nat, Rat, Variant};
 use crate::{
     capsule::Capsule,
     error::{Error, Fallible},
 };
-use urashima_util::Index;
+use urashima_util::{num::ToPrimitive, Index};
 
 pub trait FromNaru<T>: Sized {
     fn from_naru(val: T, ctx: &mut Capsule<'_>) -> Fallible<Self>;
 }
 
-impl FromNaru<Variant> for bool {
+impl FromNaru<Variant> for Variant {
     fn from_naru(val: Variant, _ctx: &mut Capsule<'_>) -> Fallible<Self> {
+        Ok(val)
+    }
+}
+
+impl FromNaru<Variant> for bool {
+    fn from_naru(val: Variant, ctx: &mut Capsule<'_>) -> Fallible<Self> {
         val.to_bool()
-            .ok_or_else(|| Error::invalid_type(symbol!("bool")))
+            .ok_or_else(|| Error::invalid_type(symbol!("bool"), val.typename(ctx)))
     }
 }
 
+/// Nats are promoted to ints implicitly.
 impl FromNaru<Variant> for Int {
-    fn from_naru(val: Variant, _ctx: &mut Capsule<'_>) -> Fallible<Self> {
-        val.to_int()
-            .cloned()
-            .ok_or_else(|| Error::invalid_type(symbol!("int")))
+    fn from_naru(val: Variant, ctx: &mut Capsule<'_>) -> Fallible<Self> {
+        match val {
+            Variant::Int(val) => Ok(val),
+            Variant::Nat(val) => Ok(val.into()),
+            _ => Err(Error::invalid_type(symbol!("int"), val.typename(ctx))),
+        }
     }
 }
 
 impl FromNaru<Variant> for Nat {
-    fn from_naru(val: Variant, _ctx: &mut Capsule<'_>) -> Fallible<Self> {
-        if let Variant::Nat(val) = val {
-            Ok(val.clone())
-        } else {
-            Err(Error::invalid_type(symbol!("nat")))
-        }
+    fn from_naru(val: Variant, ctx: &mut Capsule<'_>) -> Fallible<Self> {
+        val.to_nat()
+            .cloned()
+            .ok_or_else(|| Error::invalid_type(symbol!("nat"), val.typename(ctx)))
+    }
+}
+
+/// Sizes and positions accept any non-negative int.
+impl FromNaru<Variant> for usize {
+    fn from_naru(val: Variant, ctx: &mut Capsule<'_>) -> Fallible<Self> {
+        let val = Int::from_naru(val, ctx)?;
+        val.to_usize()
+            .ok_or_else(|| Error::value(format!("{} is not a valid size", val)))
+    }
+}
+
+/// Ints and nats are promoted to rats implicitly.
+impl FromNaru<Variant> for Rat {
+    fn from_naru(val: Variant, ctx: &mut Capsule<'_>) -> Fallible<Self> {
+        val.promote_to_rat()
+            .ok_or_else(|| Error::invalid_type(symbol!("rat"), val.typename(ctx)))
     }
 }
 
 impl FromNaru<Variant> for String {
-    fn from_naru(val: Variant, _ctx: &mut Capsule<'_>) -> Fallible<Self> {
+    fn from_naru(val: Variant, ctx: &mut Capsule<'_>) -> Fallible<Self> {
         if let Variant::Str(val) = val {
-            Ok(val.clone())
+            Ok(val)
         } else {
-            Err(Error::invalid_type(symbol!("str")))
+            Err(Error::invalid_type(symbol!("str"), val.typename(ctx)))
         }
     }
 }
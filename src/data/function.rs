@@ -1,35 +1,102 @@
-use super::{Symbol, Variant};
+use std::sync::Arc;
+
+use super::{
+    nominal::{Constructor, Tag},
+    Symbol, Variant,
+};
 use crate::{
     capsule::Capsule,
-    error::Fallible,
+    environment::Scope,
+    error::{ControlFlow, Error, Fallible},
     eval::{eval_in_context, Evaluate},
-    // environment::Environment,
+    inst::{self, FunctionCode},
 };
 use urashima_ast::expr::{block::BlockExpression, ExprIndex};
 
 #[derive(Clone)]
 pub struct Function {
-    parameters: Vec<Symbol>,
-    body: BlockExpression,
-    // environment: Environment,
+    body: Body,
+    environment: Arc<Scope>,
+}
+
+#[derive(Clone)]
+enum Body {
+    Tree(Vec<Symbol>, BlockExpression),
+    Code(Arc<FunctionCode>),
+    Constructor(Arc<Constructor>),
 }
 
 impl Function {
-    pub fn new(_ctx: &mut Capsule<'_>, parameters: Vec<Symbol>, body: BlockExpression) -> Self {
-        Function { parameters, body }
+    pub fn new(ctx: &mut Capsule<'_>, parameters: Vec<Symbol>, body: BlockExpression) -> Self {
+        Function {
+            body: Body::Tree(parameters, body),
+            environment: Arc::new(ctx.environment.capture()),
+        }
+    }
+
+    /// Function translated to instructions, which runs on the virtual machine.
+    pub(crate) fn from_code(code: Arc<FunctionCode>, environment: Scope) -> Self {
+        Function {
+            body: Body::Code(code),
+            environment: Arc::new(environment),
+        }
+    }
+
+    /// Constructor of a type declared with `type`
+    pub(crate) fn constructor(constructor: Constructor) -> Self {
+        Function {
+            body: Body::Constructor(Arc::new(constructor)),
+            environment: Arc::new(Scope::default()),
+        }
+    }
+
+    /// Tag of the records made by the function, if it is the constructor of a type
+    pub(crate) fn constructor_tag(&self) -> Option<&Tag> {
+        match &self.body {
+            Body::Constructor(constructor) => Some(constructor.tag()),
+            _ => None,
+        }
+    }
+
+    /// Make the function visible to itself under the given name, so that it can be called recursively.
+    pub(crate) fn bind_self(&mut self, name: &str, value: Variant) {
+        Arc::make_mut(&mut self.environment).bind(name, value);
+    }
+
+    /// Bindings captured when the function is created
+    pub(crate) fn environment(&self) -> &Scope {
+        &self.environment
     }
 
     pub fn call(&self, ctx: &mut Capsule<'_>, arguments: &[ExprIndex]) -> Fallible<Variant> {
-        let Function { parameters, body } = self;
         let args: Vec<_> = arguments
             .iter()
             .map(|arg| arg.eval(ctx))
             .collect::<Result<_, _>>()?;
-        let mut g = ctx.push();
+        self.apply(ctx, args)
+    }
+
+    /// Call the function with arguments which are already evaluated.
+    pub fn apply(&self, ctx: &mut Capsule<'_>, args: Vec<Variant>) -> Fallible<Variant> {
+        let (parameters, body) = match &self.body {
+            Body::Tree(parameters, body) => (parameters, body),
+            Body::Code(code) => return inst::call(&mut ctx.enter(&self.environment), code, args),
+            Body::Constructor(constructor) => return constructor.construct(ctx, args),
+        };
+        if let Some(name) = parameters.get(args.len()) {
+            return Err(Error::name(name.clone()));
+        }
+        let mut g = ctx.enter(&self.environment);
         for (name, val) in parameters.iter().zip(args) {
             g.bind(&name, val);
         }
-        Ok(eval_in_context(body, &mut g)?)
+        match eval_in_context(body, &mut g) {
+            Err(e) => match e.as_control_flow() {
+                Some(ControlFlow::Return(value)) => Ok(value.clone()),
+                _ => Err(e),
+            },
+            result => result,
+        }
     }
 }
 
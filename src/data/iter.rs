@@ -0,0 +1,81 @@
+use std::fmt;
+use std::vec;
+
+use super::{Int, Variant};
+use crate::{
+    capsule::Capsule,
+    error::{Error, Fallible},
+};
+
+/// A half-open range of ints, made by `start until(end)` or `start to(end)`.
+#[derive(Clone, PartialEq)]
+pub struct Range {
+    pub start: Int,
+    pub end: Int,
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} until({})", self.start, self.end)
+    }
+}
+
+/// State of a `for` loop over a value.
+///
+/// Vecs, strings, ranges and records are iterated directly. Any other value, or a record which
+/// has an `iter` method, is asked for an iterator with `iter()`, and then the iterator's `next()`
+/// is called for each item; it returns `(item,)` for an item, or `()` when it is exhausted.
+pub(crate) enum Iter {
+    Items(vec::IntoIter<Variant>),
+    Range(Int, Int),
+    Protocol(Variant),
+}
+
+impl Iter {
+    pub(crate) fn new(ctx: &mut Capsule<'_>, value: Variant) -> Fallible<Self> {
+        let iter = match value {
+            Variant::Vec(items) => Iter::Items(items.into_iter()),
+            Variant::Str(s) => {
+                let chars: Vec<_> = s.chars().map(|c| Variant::from(c.to_string())).collect();
+                Iter::Items(chars.into_iter())
+            }
+            Variant::Range(range) => Iter::Range(range.start, range.end),
+            Variant::Record(record) if record.get("iter").is_none() => {
+                let values: Vec<_> = record
+                    .iter()
+                    .filter_map(|(_, idx)| ctx.environment.get(idx).cloned())
+                    .collect();
+                Iter::Items(values.into_iter())
+            }
+            value => Iter::Protocol(value.invoke(ctx, "iter".into(), &[])?),
+        };
+        Ok(iter)
+    }
+
+    pub(crate) fn next(&mut self, ctx: &mut Capsule<'_>) -> Fallible<Option<Variant>> {
+        match self {
+            Iter::Items(items) => Ok(items.next()),
+            Iter::Range(start, end) => {
+                if start >= end {
+                    return Ok(None);
+                }
+                let item = start.clone();
+                *start += 1;
+                Ok(Some(Variant::Int(item)))
+            }
+            Iter::Protocol(iter) => match iter.invoke(ctx, "next".into(), &[])? {
+                Variant::Record(ref record) if record.is_empty() => Ok(None),
+                Variant::Record(ref record) if record.len() == 1 => {
+                    let (_, idx) = record.iter().next().expect("unreachable");
+                    Ok(Some(
+                        ctx.environment
+                            .get(idx)
+                            .cloned()
+                            .unwrap_or_else(Variant::unit),
+                    ))
+                }
+                other => Err(Error::invalid_type("(any,)", other.typename(ctx))),
+            },
+        }
+    }
+}
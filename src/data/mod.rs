@@ -1,7 +1,10 @@
 pub mod convert;
 pub mod function;
 pub mod invoke;
+pub mod iter;
+pub mod nominal;
 pub mod num;
+pub mod pattern;
 pub mod record;
 pub mod variant;
 
@@ -9,8 +12,10 @@ pub use self::{
     convert::FromNaru,
     function::Function,
     invoke::{Invoke, NativeMethod},
-    num::{Int, Nat},
-    record::Record,
+    iter::Range,
+    nominal::Tag,
+    num::{Int, Nat, Rat},
+    record::{Record, RecordType},
     variant::Variant,
 };
 pub use urashima_util::{symbol, Symbol};
@@ -0,0 +1,116 @@
+use std::sync::Arc;
+
+use urashima_ast::statement::types::{FieldDeclaration, TypeDeclaration, TypeDefinition};
+
+use super::{Function, Record, Symbol, Variant};
+use crate::{
+    capsule::Capsule,
+    error::{Error, Fallible},
+};
+
+/// Identity of a type declared with `type`. Records made by the constructors of different
+/// declarations are never equal, even if they have the same fields.
+#[derive(Debug)]
+pub struct Tag {
+    /// Name of the declared type
+    pub name: Symbol,
+    /// Name of the variant, if the type is a tagged union
+    pub variant: Option<Symbol>,
+}
+
+impl Tag {
+    /// Name of the constructor which makes the records of the tag
+    pub fn constructor(&self) -> &Symbol {
+        self.variant.as_ref().unwrap_or(&self.name)
+    }
+}
+
+/// Makes records of a nominal type from the field values, in order of declaration.
+pub(crate) struct Constructor {
+    tag: Arc<Tag>,
+    /// Labels and declared types of the fields
+    fields: Vec<(Symbol, Symbol)>,
+}
+
+impl Constructor {
+    pub(crate) fn tag(&self) -> &Tag {
+        &self.tag
+    }
+
+    pub(crate) fn construct(&self, ctx: &mut Capsule<'_>, args: Vec<Variant>) -> Fallible<Variant> {
+        if args.len() != self.fields.len() {
+            return Err(Error::value(format!(
+                "{} takes {} fields, but {} were given",
+                self.tag.constructor(),
+                self.fields.len(),
+                args.len()
+            )));
+        }
+        let mut fields = Vec::with_capacity(args.len());
+        for ((label, ty), value) in self.fields.iter().zip(args) {
+            let typename = value.typename(ctx);
+            if !type_matches(ty, &typename) {
+                return Err(Error::invalid_type(ty.clone(), typename));
+            }
+            fields.push((label.clone(), ctx.environment.boxed(value)));
+        }
+        let record: Record = fields.into_iter().collect();
+        Ok(Variant::Record(record.with_tag(Arc::clone(&self.tag))))
+    }
+}
+
+/// `any` admits every value, and the name of a generic type like `vec` admits any of its
+/// instances.
+fn type_matches(declared: &str, typename: &str) -> bool {
+    declared == "any"
+        || declared == typename
+        || typename
+            .strip_prefix(declared)
+            .is_some_and(|rest| rest.starts_with('['))
+}
+
+/// Create the type of the declaration, and returns the values to be bound with their names:
+/// the constructors, or the only value of a variant which is declared without fields.
+pub(crate) fn declare_type(
+    ctx: &mut Capsule<'_>,
+    decl: &TypeDeclaration,
+) -> Vec<(Symbol, Variant)> {
+    let name = &decl.name.node;
+    match &decl.definition {
+        TypeDefinition::Record(fields) => {
+            let tag = Tag {
+                name: name.clone(),
+                variant: None,
+            };
+            vec![(name.clone(), constructor(ctx, tag, fields))]
+        }
+        TypeDefinition::Union(variants) => variants
+            .iter()
+            .map(|variant| {
+                let tag = Tag {
+                    name: name.clone(),
+                    variant: Some(variant.name.node.clone()),
+                };
+                let value = match &variant.fields {
+                    Some(fields) => constructor(ctx, tag, fields),
+                    None => Variant::Record(Record::unit().with_tag(Arc::new(tag))),
+                };
+                (variant.name.node.clone(), value)
+            })
+            .collect(),
+    }
+}
+
+fn constructor(ctx: &mut Capsule<'_>, tag: Tag, fields: &[FieldDeclaration]) -> Variant {
+    let constructor = Constructor {
+        tag: Arc::new(tag),
+        fields: fields
+            .iter()
+            .map(|f| (f.label.clone(), f.ty.node.clone()))
+            .collect(),
+    };
+    let idx = ctx
+        .environment
+        .add_function(Function::constructor(constructor));
+    Variant::Fn(idx)
+}
@@ -1 +1 @@
-pub use urashima_util::num::{Int, Nat};
+pub use urashima_util::num::{Int, Nat, Rat};
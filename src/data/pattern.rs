@@ -0,0 +1,101 @@
+use std::ptr;
+
+use urashima_ast::expr::Pattern;
+
+use super::{nominal::Tag, Symbol, Variant};
+use crate::capsule::Capsule;
+
+/// Match the value against the pattern of a `switch` arm.
+///
+/// `scope` holds the values of `Pattern::references`, in that order; unit stands for a bound
+/// name which is not defined in the scope. Returns the values bound by the pattern, in the
+/// order of `Pattern::bindings`, or `None` if the value doesn't match. Literals are compared
+/// with `==`, so `1` matches `1.0` too. Tags are compared by identity, like `==` does.
+pub(crate) fn match_pattern(
+    ctx: &Capsule<'_>,
+    pattern: &Pattern,
+    value: &Variant,
+    scope: &[Variant],
+) -> Option<Vec<Variant>> {
+    let scope: Vec<_> = pattern.references().into_iter().zip(scope).collect();
+    let mut bound = vec![];
+    if match_into(ctx, &scope, pattern, value, &mut bound) {
+        Some(bound)
+    } else {
+        None
+    }
+}
+
+fn match_into(
+    ctx: &Capsule<'_>,
+    scope: &[(&Symbol, &Variant)],
+    pattern: &Pattern,
+    value: &Variant,
+    bound: &mut Vec<Variant>,
+) -> bool {
+    match pattern {
+        Pattern::Wildcard => true,
+        Pattern::Bool(b) => value.to_bool() == Some(*b),
+        Pattern::Integral(i) => Variant::Int(i.clone()).equals(value, ctx),
+        Pattern::Rational(r) => Variant::from(r.clone()).equals(value, ctx),
+        Pattern::Str(s) => matches!(value, Variant::Str(v) if v == s),
+        Pattern::Binding(name) => {
+            if let Some(tag) = lookup(scope, name).and_then(variant_tag) {
+                if !has_tag(value, tag) {
+                    return false;
+                }
+            }
+            bound.push(value.clone());
+            true
+        }
+        Pattern::Record { tag, fields, rest } => {
+            let record = match value.as_record() {
+                Some(record) => record,
+                None => return false,
+            };
+            if let Some(name) = tag {
+                let tag = lookup(scope, name).and_then(|v| constructor_tag(ctx, v));
+                match tag {
+                    Some(tag) if has_tag(value, tag) => {}
+                    _ => return false,
+                }
+            }
+            if !rest && record.len() != fields.len() {
+                return false;
+            }
+            fields.iter().all(|(label, pattern)| {
+                match record.field(label).and_then(|idx| ctx.environment.get(idx)) {
+                    Some(field) => match_into(ctx, scope, &pattern.node, field, bound),
+                    None => false,
+                }
+            })
+        }
+    }
+}
+
+fn lookup<'v>(scope: &[(&Symbol, &'v Variant)], name: &Symbol) -> Option<&'v Variant> {
+    scope.iter().find(|(n, _)| *n == name).map(|(_, v)| *v)
+}
+
+/// Tag of a variant declared without fields
+fn variant_tag(value: &Variant) -> Option<&Tag> {
+    match value {
+        Variant::Record(record) if record.is_empty() => record.tag(),
+        _ => None,
+    }
+}
+
+/// Tag of the records made by the constructor, or of the variant declared without fields
+fn constructor_tag<'v>(ctx: &'v Capsule<'_>, value: &'v Variant) -> Option<&'v Tag> {
+    match value {
+        Variant::Fn(idx) => ctx.environment.get_function(*idx)?.constructor_tag(),
+        _ => variant_tag(value),
+    }
+}
+
+fn has_tag(value: &Variant, tag: &Tag) -> bool {
+    match value.as_record().and_then(|record| record.tag()) {
+        Some(t) => ptr::eq(t, tag),
+        None => false,
+    }
+}
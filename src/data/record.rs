@@ -1,19 +1,108 @@
 use std::collections::HashMap;
 use std::fmt::{self, Display};
 use std::iter::FromIterator;
+use std::sync::Arc;
 
 use urashima_util::Index;
 
-use super::{Symbol, Variant};
+use super::{symbol, Symbol, Tag, Variant};
+use crate::capsule::Capsule;
 
 #[derive(Clone)]
 pub struct Record {
     pub(crate) fields: Vec<Field>,
+    /// The nominal type, if the record is made by a constructor
+    tag: Option<Arc<Tag>>,
 }
 
 impl Record {
     pub fn unit() -> Self {
-        Record { fields: Vec::new() }
+        Record {
+            fields: Vec::new(),
+            tag: None,
+        }
+    }
+
+    pub(crate) fn with_tag(mut self, tag: Arc<Tag>) -> Self {
+        self.tag = Some(tag);
+        self
+    }
+
+    pub fn tag(&self) -> Option<&Tag> {
+        self.tag.as_deref()
+    }
+
+    /// Whether both records are anonymous, or made by the same constructor
+    pub fn same_tag(&self, other: &Record) -> bool {
+        match (&self.tag, &other.tag) {
+            (None, None) => true,
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    pub fn get(&self, label: &str) -> Option<Index<Variant>> {
+        self.fields
+            .iter()
+            .find(|f| f.label == *label)
+            .map(|f| f.value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Symbol, Index<Variant>)> {
+        self.fields.iter().map(|f| (&f.label, f.value))
+    }
+
+    /// The structural type of the record, with the types of the current field values.
+    pub fn record_type(&self, ctx: &mut Capsule<'_>) -> RecordType {
+        self.record_type_with(ctx, &mut Vec::new())
+    }
+
+    pub(crate) fn record_type_with(
+        &self,
+        ctx: &mut Capsule<'_>,
+        seen: &mut Vec<Index<Variant>>,
+    ) -> RecordType {
+        let mut ty = self.shape();
+        for f in &self.fields {
+            let typename = match ctx.environment.get(f.value).cloned() {
+                Some(value) => value.typename_with(ctx, seen),
+                None => symbol!("any"),
+            };
+            ty.types.push(typename);
+        }
+        ty
+    }
+
+    /// The labels of the record, without the field types.
+    /// Positional labels are left out since they are implied by the position.
+    fn shape(&self) -> RecordType {
+        RecordType {
+            length: self.fields.len(),
+            labels: self
+                .fields
+                .iter()
+                .enumerate()
+                .filter(|(i, f)| f.label != *i.to_string())
+                .map(|(i, f)| (f.label.clone(), i))
+                .collect(),
+            types: Vec::new(),
+        }
+    }
+
+    /// Find the field by its label, or by its position if the label is a number.
+    pub fn field(&self, label: &Symbol) -> Option<Index<Variant>> {
+        self.get(label).or_else(|| {
+            let idx = label.parse::<usize>().ok()?;
+            self.fields.get(idx).map(|f| f.value)
+        })
     }
 }
 
@@ -32,7 +121,7 @@ impl FromIterator<(Symbol, Index<Variant>)> for Record {
             .into_iter()
             .map(|(label, value)| Field { label, value })
             .collect();
-        Record { fields }
+        Record { fields, tag: None }
     }
 }
 
@@ -45,9 +134,34 @@ pub struct Field {
 pub struct RecordType {
     length: usize,
     labels: HashMap<Symbol, usize>,
+    types: Vec<Symbol>,
 }
 
 impl RecordType {
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// The label of the field at the position, if it is not positional.
+    pub fn label(&self, idx: usize) -> Option<&Symbol> {
+        self.labels
+            .iter()
+            .find(|(_, i)| **i == idx)
+            .map(|(label, _)| label)
+    }
+
+    /// The type of the field at the position. Unknown types are `any`.
+    pub fn field_type(&self, idx: usize) -> Option<&str> {
+        if idx >= self.length {
+            return None;
+        }
+        Some(self.types.get(idx).map_or("any", |t| t))
+    }
+
     pub(crate) fn to_index(&self, label: Symbol) -> Option<usize> {
         self.labels.get(&label).copied()
     }
@@ -59,23 +173,18 @@ impl Display for RecordType {
         for (label, idx) in &self.labels {
             fields[*idx] = Some(label);
         }
-        let mut fields = fields.into_iter();
         f.write_str("(")?;
-        if let Some(label) = fields.next() {
-            if let Some(label) = label {
-                write!(f, "{}: ", label)?;
-            }
-            write!(f, "any")?;
-            if fields.len() == 0 {
-                f.write_str(",")?;
+        for (i, label) in fields.into_iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
             }
-        }
-        for label in fields {
-            f.write_str(", ")?;
             if let Some(label) = label {
                 write!(f, "{}: ", label)?;
             }
-            write!(f, "any")?;
+            f.write_str(self.field_type(i).unwrap_or("any"))?;
+        }
+        if self.length == 1 {
+            f.write_str(",")?;
         }
         f.write_str(")")?;
         Ok(())
@@ -91,6 +200,7 @@ mod test {
         let rec = RecordType {
             length: 0,
             labels: HashMap::new(),
+            types: Vec::new(),
         };
         assert_eq!("()", rec.to_string());
     }
@@ -100,6 +210,7 @@ mod test {
         let rec = RecordType {
             length: 1,
             labels: HashMap::new(),
+            types: Vec::new(),
         };
         assert_eq!("(any,)", rec.to_string());
     }
@@ -109,6 +220,7 @@ mod test {
         let mut rec = RecordType {
             length: 1,
             labels: HashMap::new(),
+            types: Vec::new(),
         };
         rec.labels.insert("foo".into(), 0);
         assert_eq!("(foo: any,)", rec.to_string());
@@ -119,6 +231,7 @@ mod test {
         let rec = RecordType {
             length: 3,
             labels: HashMap::new(),
+            types: Vec::new(),
         };
         assert_eq!("(any, any, any)", rec.to_string());
     }
@@ -128,6 +241,7 @@ mod test {
         let mut rec = RecordType {
             length: 3,
             labels: HashMap::new(),
+            types: Vec::new(),
         };
         rec.labels.insert("x".into(), 0);
         rec.labels.insert("y".into(), 1);
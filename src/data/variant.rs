@@ -1,9 +1,17 @@
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::str::FromStr;
 
 use lazy_static::lazy_static;
-use urashima_util::{num::Signed, Index};
+use urashima_util::{
+    num::{Decimal, Signed, Zero},
+    Index,
+};
 
-use super::{symbol, Function, Int, Invoke, Nat, NativeMethod, Record, Symbol};
+use super::{
+    symbol, FromNaru, Function, Int, Invoke, Nat, NativeMethod, Range, Rat, Record, RecordType,
+    Symbol,
+};
 use crate::{
     capsule::Capsule,
     error::{Error, Fallible},
@@ -14,8 +22,11 @@ pub enum Variant {
     Bool(bool),
     Int(Int),
     Nat(Nat),
+    Rat(Box<Rat>),
     Str(String),
     Record(Record),
+    Vec(Vec<Variant>),
+    Range(Box<Range>),
     Fn(Index<Function>),
     Ref(Index<Variant>),
 }
@@ -26,18 +37,51 @@ impl Variant {
         Variant::Record(Record::unit())
     }
 
-    pub fn typename(&self, _ctx: &mut Capsule<'_>) -> Symbol {
+    pub fn typename(&self, ctx: &mut Capsule<'_>) -> Symbol {
+        self.typename_with(ctx, &mut Vec::new())
+    }
+
+    /// `seen` holds the references being visited, to stop at cyclic references.
+    pub(crate) fn typename_with(
+        &self,
+        ctx: &mut Capsule<'_>,
+        seen: &mut Vec<Index<Variant>>,
+    ) -> Symbol {
         match self {
             Variant::Bool(_) => symbol!("bool"),
             Variant::Int(_) => symbol!("int"),
             Variant::Nat(_) => symbol!("nat"),
+            Variant::Rat(_) => symbol!("rat"),
             Variant::Str(_) => symbol!("str"),
-            Variant::Record(_) => Symbol::from("()"),
+            Variant::Record(rec) => match rec.tag() {
+                Some(tag) => tag.name.clone(),
+                None => Symbol::from(rec.record_type_with(ctx, seen).to_string()),
+            },
+            Variant::Vec(items) => {
+                let mut types = items.iter().map(|item| item.typename_with(ctx, seen));
+                let first = types.next().unwrap_or(symbol!("any"));
+                let same = types.all(|t| t == first);
+                Symbol::from(format!("vec[{}]", if same { &*first } else { "any" }))
+            }
+            Variant::Range(_) => symbol!("range"),
             Variant::Fn(_) => symbol!("fn"),
-            Variant::Ref(_) => Symbol::from("ref[\\?]"),
+            Variant::Ref(idx) if seen.contains(idx) => symbol!("ref"),
+            Variant::Ref(idx) => match ctx.environment.get(*idx).cloned() {
+                Some(inner) => {
+                    seen.push(*idx);
+                    let typename = inner.typename_with(ctx, seen);
+                    seen.pop();
+                    Symbol::from(format!("ref[{}]", typename))
+                }
+                None => symbol!("ref"),
+            },
         }
     }
 
+    pub fn record_type(&self, ctx: &mut Capsule<'_>) -> Option<RecordType> {
+        self.as_record().map(|rec| rec.record_type(ctx))
+    }
+
     pub fn as_record(&self) -> Option<&Record> {
         if let Variant::Record(val) = self {
             Some(val)
@@ -70,31 +114,485 @@ impl Variant {
         }
     }
 
+    pub fn to_nat(&self) -> Option<&Nat> {
+        if let Variant::Nat(val) = self {
+            Some(val)
+        } else {
+            None
+        }
+    }
+
+    pub fn to_rat(&self) -> Option<&Rat> {
+        if let Variant::Rat(val) = self {
+            Some(val)
+        } else {
+            None
+        }
+    }
+
+    pub fn is_number(&self) -> bool {
+        matches!(self, Variant::Int(_) | Variant::Nat(_) | Variant::Rat(_))
+    }
+
+    /// Converts any number to a rat
+    pub(crate) fn promote_to_rat(&self) -> Option<Rat> {
+        match self {
+            Variant::Int(val) => Some(Rat::from_integer(val.clone())),
+            Variant::Nat(val) => Some(Rat::from_integer(val.clone().into())),
+            Variant::Rat(val) => Some((**val).clone()),
+            _ => None,
+        }
+    }
+
+    /// Convert the value into a string, as format strings do. A record can override this with
+    /// its own `to_str` method.
+    pub fn to_str(&self, ctx: &mut Capsule<'_>) -> Fallible<String> {
+        match self {
+            Variant::Str(s) => Ok(s.clone()),
+            _ => match self.invoke(ctx, "to_str".into(), &[])? {
+                Variant::Str(s) => Ok(s),
+                other => Err(Error::invalid_type(symbol!("str"), other.typename(ctx))),
+            },
+        }
+    }
+
+    /// The built-in string conversion. Strings nested in other values are written as literals.
+    fn display(&self, ctx: &Capsule<'_>, nested: bool, seen: &mut Vec<Index<Variant>>) -> String {
+        match self {
+            Variant::Bool(val) => val.to_string(),
+            Variant::Int(val) => val.to_string(),
+            Variant::Nat(val) => val.to_string(),
+            Variant::Rat(val) => Decimal(val).to_string(),
+            Variant::Str(val) if nested => urashima_ast::Str(val).to_string(),
+            Variant::Str(val) => val.clone(),
+            Variant::Record(record) if record.is_empty() && record.tag().is_some() => {
+                record.tag().expect("unreachable").constructor().to_string()
+            }
+            Variant::Record(record) => {
+                let mut buf = match record.tag() {
+                    Some(tag) => format!("{}(", tag.constructor()),
+                    None => String::from("("),
+                };
+                for (i, (label, idx)) in record.iter().enumerate() {
+                    if i > 0 {
+                        buf.push_str(", ");
+                    }
+                    if *label != *i.to_string() {
+                        buf.push_str(label);
+                        buf.push_str(": ");
+                    }
+                    match ctx.environment.get(idx) {
+                        Some(value) => buf.push_str(&value.display(ctx, true, seen)),
+                        None => buf.push('?'),
+                    }
+                }
+                if record.len() == 1 && record.tag().is_none() {
+                    buf.push(',');
+                }
+                buf.push(')');
+                buf
+            }
+            Variant::Vec(items) => {
+                let items: Vec<_> = items
+                    .iter()
+                    .map(|item| item.display(ctx, true, seen))
+                    .collect();
+                format!("[{}]", items.join(", "))
+            }
+            Variant::Range(range) => range.to_string(),
+            Variant::Fn(_) => "fn".to_string(),
+            Variant::Ref(idx) if seen.contains(idx) => "new ...".to_string(),
+            Variant::Ref(idx) => match ctx.environment.get(*idx) {
+                Some(inner) => {
+                    seen.push(*idx);
+                    let inner = inner.display(ctx, true, seen);
+                    seen.pop();
+                    format!("new {}", inner)
+                }
+                None => "new ?".to_string(),
+            },
+        }
+    }
+
+    /// The value as written in error messages, with strings quoted
+    pub(crate) fn repr(&self, ctx: &Capsule<'_>) -> String {
+        self.display(ctx, true, &mut Vec::new())
+    }
+
+    /// Structural equality
+    pub fn equals(&self, other: &Variant, ctx: &Capsule<'_>) -> bool {
+        match (self, other) {
+            (Variant::Bool(a), Variant::Bool(b)) => a == b,
+            (Variant::Int(a), Variant::Int(b)) => a == b,
+            (Variant::Nat(a), Variant::Nat(b)) => a == b,
+            (Variant::Int(_), _) | (Variant::Nat(_), _) | (Variant::Rat(_), _)
+                if other.is_number() =>
+            {
+                self.compare(other) == Some(Ordering::Equal)
+            }
+            (Variant::Str(a), Variant::Str(b)) => a == b,
+            (Variant::Record(a), Variant::Record(b)) => {
+                a.same_tag(b)
+                    && a.len() == b.len()
+                    && a.iter().all(|(label, a)| {
+                        let a = ctx.environment.get(a);
+                        let b = b.get(label).and_then(|b| ctx.environment.get(b));
+                        match (a, b) {
+                            (Some(a), Some(b)) => a.equals(b, ctx),
+                            _ => false,
+                        }
+                    })
+            }
+            (Variant::Vec(a), Variant::Vec(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.equals(b, ctx))
+            }
+            (Variant::Range(a), Variant::Range(b)) => a == b,
+            (Variant::Fn(a), Variant::Fn(b)) => a == b,
+            (Variant::Ref(a), Variant::Ref(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    /// Ordering between values of the same ordered type, or between numbers
+    pub fn compare(&self, other: &Variant) -> Option<Ordering> {
+        match (self, other) {
+            (Variant::Bool(a), Variant::Bool(b)) => Some(a.cmp(b)),
+            (Variant::Int(a), Variant::Int(b)) => Some(a.cmp(b)),
+            (Variant::Nat(a), Variant::Nat(b)) => Some(a.cmp(b)),
+            (Variant::Int(a), Variant::Nat(b)) => Some(a.cmp(&Int::from(b.clone()))),
+            (Variant::Nat(a), Variant::Int(b)) => Some(Int::from(a.clone()).cmp(b)),
+            (Variant::Rat(_), _) | (_, Variant::Rat(_)) => {
+                Some(self.promote_to_rat()?.cmp(&other.promote_to_rat()?))
+            }
+            (Variant::Str(a), Variant::Str(b)) => Some(a.cmp(b)),
+            _ => None,
+        }
+    }
+
+    /// Read the field of a record by its label or position.
+    pub fn field(&self, ctx: &mut Capsule<'_>, label: &Symbol) -> Fallible<Variant> {
+        let record = match self {
+            Variant::Record(record) => record,
+            _ => return Err(Error::no_field(self.typename(ctx), label.clone())),
+        };
+        let idx = record
+            .field(label)
+            .ok_or_else(|| Error::no_field(record.record_type(ctx).to_string(), label.clone()))?;
+        ctx.environment.get(idx).cloned().ok_or_else(Error::runtime)
+    }
+
+    /// Get the item of a vec at the position.
+    pub(crate) fn index(&self, ctx: &mut Capsule<'_>, index: Variant) -> Fallible<Variant> {
+        let items = match self {
+            Variant::Vec(items) => items,
+            _ => return Err(Error::invalid_type(symbol!("vec"), self.typename(ctx))),
+        };
+        let index = usize::from_naru(index, ctx)?;
+        items.get(index).cloned().ok_or_else(|| {
+            Error::value(format!(
+                "index {} out of range for vec of length {}",
+                index,
+                items.len()
+            ))
+        })
+    }
+
     pub fn invoke(
         &self,
         ctx: &mut Capsule<'_>,
         method: Symbol,
         arguments: &[Variant],
     ) -> Fallible<Variant> {
+        let result = match self {
+            Variant::Bool(val) => VTABLE_BOOL
+                .get(&method)
+                .map(|f| f.invoke(ctx, val, arguments)),
+            Variant::Int(val) => VTABLE_INT
+                .get(&method)
+                .map(|f| f.invoke(ctx, val, arguments)),
+            Variant::Nat(val) => VTABLE_NAT
+                .get(&method)
+                .map(|f| f.invoke(ctx, val, arguments)),
+            Variant::Rat(val) => VTABLE_RAT
+                .get(&method)
+                .map(|f| f.invoke(ctx, &**val, arguments)),
+            Variant::Str(val) => VTABLE_STR
+                .get(&method)
+                .map(|f| f.invoke(ctx, val, arguments)),
+            Variant::Record(val) => self.invoke_record(ctx, val, &method, arguments),
+            Variant::Vec(val) => VTABLE_VEC
+                .get(&method)
+                .map(|f| f.invoke(ctx, val, arguments)),
+            Variant::Range(val) => VTABLE_RANGE
+                .get(&method)
+                .map(|f| f.invoke(ctx, &**val, arguments)),
+            Variant::Ref(val) => VTABLE_REF
+                .get(&method)
+                .map(|f| f.invoke(ctx, val, arguments)),
+            _ => None,
+        };
+        if let Some(result) = result {
+            return result;
+        }
+        match VTABLE_ANY.get(&method) {
+            Some(f) => f.invoke(ctx, self, arguments),
+            None => Err(Error::no_method(self.typename(ctx), method)),
+        }
+    }
+
+    /// Whether the method is a function stored in the record rather than a native method, so
+    /// that calling it makes a frame of the traceback.
+    pub(crate) fn has_fn_method(&self, ctx: &Capsule<'_>, method: &str) -> bool {
         match self {
-            Variant::Int(val) => {
-                let f = VTABLE_INT.get(&method).ok_or_else(|| Error::name(method))?;
-                f.invoke(ctx, val, arguments)
+            Variant::Record(record) => matches!(
+                record.get(method).and_then(|idx| ctx.environment.get(idx)),
+                Some(Variant::Fn(_))
+            ),
+            _ => false,
+        }
+    }
+
+    /// Call the function stored in the record under the method name, passing the record itself
+    /// as the first argument.
+    fn invoke_record(
+        &self,
+        ctx: &mut Capsule<'_>,
+        record: &Record,
+        method: &str,
+        arguments: &[Variant],
+    ) -> Option<Fallible<Variant>> {
+        let field = record.get(method)?;
+        let f = match ctx.environment.get(field) {
+            Some(Variant::Fn(f)) => *f,
+            _ => return None,
+        };
+        let f = match ctx.environment.get_function(f) {
+            Some(f) => f.clone(),
+            None => return Some(Err(Error::runtime())),
+        };
+        let mut args = Vec::with_capacity(arguments.len() + 1);
+        args.push(self.clone());
+        args.extend_from_slice(arguments);
+        Some(f.apply(ctx, args))
+    }
+}
+
+fn compare_with(
+    ctx: &mut Capsule<'_>,
+    this: &Variant,
+    other: &Variant,
+    pred: impl Fn(Ordering) -> bool,
+) -> Fallible<bool> {
+    let ord = this
+        .compare(other)
+        .ok_or_else(|| Error::invalid_type(this.typename(ctx), other.typename(ctx)))?;
+    Ok(pred(ord))
+}
+
+/// Arithmetic between an int and another number. A rat operand promotes the int to a rat.
+fn int_arith(ctx: &mut Capsule<'_>, this: &Int, other: Variant, op: &str) -> Fallible<Variant> {
+    let other = match other {
+        Variant::Rat(_) => {
+            let this = Rat::from_integer(this.clone());
+            return Variant::from(this).invoke(ctx, op.into(), &[other]);
+        }
+        other => Int::from_naru(other, ctx)?,
+    };
+    let res = match op {
+        "+" => this + other,
+        "-" => this - other,
+        "*" => this * other,
+        "/" => {
+            if other.is_zero() {
+                return Err(Error::value("division by zero"));
+            }
+            this / other
+        }
+        _ => unreachable!(),
+    };
+    Ok(Variant::Int(res))
+}
+
+/// Arithmetic between a nat and another number. The result stays a nat only if both operands
+/// are nats; otherwise the nat is promoted and the operation is done on ints.
+fn nat_arith(ctx: &mut Capsule<'_>, this: &Nat, other: Variant, op: &str) -> Fallible<Variant> {
+    let other = match other {
+        Variant::Nat(other) => other,
+        Variant::Int(_) | Variant::Rat(_) => {
+            return Variant::Int(this.clone().into()).invoke(ctx, op.into(), &[other]);
+        }
+        other => return Err(Error::invalid_type(symbol!("nat"), other.typename(ctx))),
+    };
+    let res = match op {
+        "+" => this + other,
+        "-" => {
+            if *this < other {
+                return Err(Error::value("nat subtraction underflow"));
             }
-            Variant::Str(val) => {
-                let f = VTABLE_STR.get(&method).ok_or_else(|| Error::name(method))?;
-                f.invoke(ctx, val, arguments)
+            this - other
+        }
+        "*" => this * other,
+        "/" => {
+            if other.is_zero() {
+                return Err(Error::value("division by zero"));
             }
-            _ => Err(Error::name(method)),
+            this / other
         }
+        _ => unreachable!(),
+    };
+    Ok(Variant::Nat(res))
+}
+
+/// Arithmetic between a rat and another number, which is promoted to a rat.
+fn rat_arith(ctx: &mut Capsule<'_>, this: &Rat, other: Variant, op: &str) -> Fallible<Variant> {
+    let other = Rat::from_naru(other, ctx)?;
+    let res = match op {
+        "+" => this + other,
+        "-" => this - other,
+        "*" => this * other,
+        "/" => {
+            if other.is_zero() {
+                return Err(Error::value("division by zero"));
+            }
+            this / other
+        }
+        _ => unreachable!(),
+    };
+    Ok(Variant::from(res))
+}
+
+/// Call a Naru function given as an argument of a native method.
+fn apply(ctx: &mut Capsule<'_>, f: &Variant, args: Vec<Variant>) -> Fallible<Variant> {
+    let f = match f.as_function(ctx) {
+        Some(f) => f.clone(),
+        None => return Err(Error::invalid_type(symbol!("fn"), f.typename(ctx))),
+    };
+    f.apply(ctx, args)
+}
+
+fn check_range(start: usize, end: usize, len: usize) -> Fallible<()> {
+    if start > end || end > len {
+        return Err(Error::value(format!(
+            "range {}..{} out of bounds for length {}",
+            start, end, len
+        )));
+    }
+    Ok(())
+}
+
+/// Slice the string by char positions, not by bytes.
+fn substring(s: &str, start: usize, end: usize) -> Fallible<String> {
+    if start > end || end > s.chars().count() {
+        return Err(Error::value(format!(
+            "substring range {}..{} out of bounds",
+            start, end
+        )));
     }
+    Ok(s.chars().skip(start).take(end - start).collect())
+}
+
+/// Parse a decimal number with an optional sign, rejecting anything else.
+fn parse_number<T: FromStr>(s: &str, typename: &str) -> Fallible<T> {
+    s.parse()
+        .map_err(|_| Error::value(format!("invalid {} literal: {:?}", typename, s)))
+}
+
+macro_rules! insert_comparisons {
+    ($m:ident, $t:ty) => {
+        $m.insert(
+            "<".into(),
+            Box::new(NativeMethod::from(
+                |ctx: &mut Capsule<'_>, this: &$t, other: Variant| {
+                    compare_with(ctx, &Variant::from(this.clone()), &other, Ordering::is_lt)
+                },
+            )),
+        );
+        $m.insert(
+            ">".into(),
+            Box::new(NativeMethod::from(
+                |ctx: &mut Capsule<'_>, this: &$t, other: Variant| {
+                    compare_with(ctx, &Variant::from(this.clone()), &other, Ordering::is_gt)
+                },
+            )),
+        );
+        $m.insert(
+            "<=".into(),
+            Box::new(NativeMethod::from(
+                |ctx: &mut Capsule<'_>, this: &$t, other: Variant| {
+                    compare_with(ctx, &Variant::from(this.clone()), &other, Ordering::is_le)
+                },
+            )),
+        );
+        $m.insert(
+            ">=".into(),
+            Box::new(NativeMethod::from(
+                |ctx: &mut Capsule<'_>, this: &$t, other: Variant| {
+                    compare_with(ctx, &Variant::from(this.clone()), &other, Ordering::is_ge)
+                },
+            )),
+        );
+    };
 }
 
 type VirtualTable<T> = HashMap<Symbol, Box<dyn Invoke<Receiver = T> + Send + Sync + 'static>>;
 
 lazy_static! {
+    static ref VTABLE_ANY: VirtualTable<Variant> = {
+        let mut m = VirtualTable::<Variant>::new();
+        m.insert(
+            "==".into(),
+            Box::new(NativeMethod::from(
+                |ctx: &mut Capsule<'_>, this: &Variant, other: Variant| Ok(this.equals(&other, ctx)),
+            )),
+        );
+        m.insert(
+            "!=".into(),
+            Box::new(NativeMethod::from(
+                |ctx: &mut Capsule<'_>, this: &Variant, other: Variant| {
+                    Ok(!this.equals(&other, ctx))
+                },
+            )),
+        );
+        m.insert(
+            "typename".into(),
+            Box::new(NativeMethod::from(
+                |ctx: &mut Capsule<'_>, this: &Variant| Ok(this.typename(ctx).to_string()),
+            )),
+        );
+        m.insert(
+            "to_str".into(),
+            Box::new(NativeMethod::from(
+                |ctx: &mut Capsule<'_>, this: &Variant| {
+                    Ok(this.display(ctx, false, &mut Vec::new()))
+                },
+            )),
+        );
+        m
+    };
+    static ref VTABLE_BOOL: VirtualTable<bool> = {
+        let mut m = VirtualTable::<bool>::new();
+        insert_comparisons!(m, bool);
+        m.insert(
+            "println".into(),
+            Box::new(NativeMethod::from(|ctx: &mut Capsule<'_>, this: &bool| {
+                ctx.print(format_args!("{}\n", this))
+            })),
+        );
+        m
+    };
     static ref VTABLE_INT: VirtualTable<Int> = {
         let mut m = VirtualTable::<Int>::new();
+        for op in &["+", "-", "*", "/"] {
+            m.insert(
+                (*op).into(),
+                Box::new(NativeMethod::from(
+                    move |ctx: &mut Capsule<'_>, this: &Int, other: Variant| {
+                        int_arith(ctx, this, other, op)
+                    },
+                )),
+            );
+        }
+        insert_comparisons!(m, Int);
         m.insert(
             "abs".into(),
             Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &Int| {
@@ -107,6 +605,37 @@ lazy_static! {
                 Ok(-this)
             })),
         );
+        m.insert(
+            "to_int".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &Int| {
+                Ok(this.clone())
+            })),
+        );
+        m.insert(
+            "until".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &Int, end: Int| {
+                Ok(Range { start: this.clone(), end })
+            })),
+        );
+        m.insert(
+            "to".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &Int, last: Int| {
+                Ok(Range { start: this.clone(), end: last + 1 })
+            })),
+        );
+        m.insert(
+            "to_rat".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &Int| {
+                Ok(Rat::from_integer(this.clone()))
+            })),
+        );
+        m.insert(
+            "to_nat".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &Int| {
+                this.to_biguint()
+                    .ok_or_else(|| Error::value("negative int cannot be a nat"))
+            })),
+        );
         m.insert(
             "println".into(),
             Box::new(NativeMethod::from(|ctx: &mut Capsule<'_>, this: &Int| {
@@ -115,8 +644,425 @@ lazy_static! {
         );
         m
     };
+    static ref VTABLE_NAT: VirtualTable<Nat> = {
+        let mut m = VirtualTable::<Nat>::new();
+        for op in &["+", "-", "*", "/"] {
+            m.insert(
+                (*op).into(),
+                Box::new(NativeMethod::from(
+                    move |ctx: &mut Capsule<'_>, this: &Nat, other: Variant| {
+                        nat_arith(ctx, this, other, op)
+                    },
+                )),
+            );
+        }
+        insert_comparisons!(m, Nat);
+        m.insert(
+            "abs".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &Nat| {
+                Ok(this.clone())
+            })),
+        );
+        m.insert(
+            "negate".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &Nat| {
+                Ok(-Int::from(this.clone()))
+            })),
+        );
+        m.insert(
+            "to_int".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &Nat| {
+                Ok(Int::from(this.clone()))
+            })),
+        );
+        m.insert(
+            "until".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &Nat, end: Int| {
+                Ok(Range { start: Int::from(this.clone()), end })
+            })),
+        );
+        m.insert(
+            "to".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &Nat, last: Int| {
+                Ok(Range { start: Int::from(this.clone()), end: last + 1 })
+            })),
+        );
+        m.insert(
+            "to_rat".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &Nat| {
+                Ok(Rat::from_integer(this.clone().into()))
+            })),
+        );
+        m.insert(
+            "to_nat".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &Nat| {
+                Ok(this.clone())
+            })),
+        );
+        m.insert(
+            "println".into(),
+            Box::new(NativeMethod::from(|ctx: &mut Capsule<'_>, this: &Nat| {
+                ctx.print(format_args!("{}\n", this))
+            })),
+        );
+        m
+    };
+    static ref VTABLE_RAT: VirtualTable<Rat> = {
+        let mut m = VirtualTable::<Rat>::new();
+        for op in &["+", "-", "*", "/"] {
+            m.insert(
+                (*op).into(),
+                Box::new(NativeMethod::from(
+                    move |ctx: &mut Capsule<'_>, this: &Rat, other: Variant| {
+                        rat_arith(ctx, this, other, op)
+                    },
+                )),
+            );
+        }
+        insert_comparisons!(m, Rat);
+        m.insert(
+            "abs".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &Rat| {
+                Ok(this.abs())
+            })),
+        );
+        m.insert(
+            "negate".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &Rat| {
+                Ok(-this.clone())
+            })),
+        );
+        m.insert(
+            "numer".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &Rat| {
+                Ok(this.numer().clone())
+            })),
+        );
+        m.insert(
+            "denom".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &Rat| {
+                Ok(this.denom().clone())
+            })),
+        );
+        m.insert(
+            "floor".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &Rat| {
+                Ok(this.floor().to_integer())
+            })),
+        );
+        m.insert(
+            "ceil".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &Rat| {
+                Ok(this.ceil().to_integer())
+            })),
+        );
+        m.insert(
+            "round".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &Rat| {
+                Ok(this.round().to_integer())
+            })),
+        );
+        m.insert(
+            "to_int".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &Rat| {
+                Ok(this.to_integer())
+            })),
+        );
+        m.insert(
+            "to_rat".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &Rat| {
+                Ok(this.clone())
+            })),
+        );
+        m.insert(
+            "println".into(),
+            Box::new(NativeMethod::from(|ctx: &mut Capsule<'_>, this: &Rat| {
+                ctx.print(format_args!("{}\n", Decimal(this)))
+            })),
+        );
+        m
+    };
+    static ref VTABLE_REF: VirtualTable<Index<Variant>> = {
+        let mut m = VirtualTable::<Index<Variant>>::new();
+        m.insert(
+            "get".into(),
+            Box::new(NativeMethod::from(
+                |ctx: &mut Capsule<'_>, this: &Index<Variant>| {
+                    ctx.environment
+                        .get(*this)
+                        .cloned()
+                        .ok_or_else(|| Error::value("dangling reference"))
+                },
+            )),
+        );
+        m.insert(
+            "set".into(),
+            Box::new(NativeMethod::from(
+                |ctx: &mut Capsule<'_>, this: &Index<Variant>, value: Variant| {
+                    let slot = ctx
+                        .environment
+                        .get_mut(*this)
+                        .ok_or_else(|| Error::value("dangling reference"))?;
+                    *slot = value;
+                    Ok(())
+                },
+            )),
+        );
+        m
+    };
+    static ref VTABLE_VEC: VirtualTable<Vec<Variant>> = {
+        let mut m = VirtualTable::<Vec<Variant>>::new();
+        m.insert(
+            "len".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &Vec<Variant>| {
+                Ok(Nat::from(this.len()))
+            })),
+        );
+        m.insert(
+            "is_empty".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &Vec<Variant>| {
+                Ok(this.is_empty())
+            })),
+        );
+        m.insert(
+            "get".into(),
+            Box::new(NativeMethod::from(
+                |ctx: &mut Capsule<'_>, this: &Vec<Variant>, index: Variant| {
+                    Variant::Vec(this.clone()).index(ctx, index)
+                },
+            )),
+        );
+        m.insert(
+            "last".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &Vec<Variant>| {
+                this.last().cloned().ok_or_else(|| Error::value("empty vec"))
+            })),
+        );
+        m.insert(
+            "push".into(),
+            Box::new(NativeMethod::from(
+                |_: &mut Capsule<'_>, this: &Vec<Variant>, item: Variant| {
+                    let mut items = this.clone();
+                    items.push(item);
+                    Ok(items)
+                },
+            )),
+        );
+        // Vecs are immutable like the other values, so `pop` returns `(rest, item)`: a vec
+        // without the last item, and the item itself.
+        m.insert(
+            "pop".into(),
+            Box::new(NativeMethod::from(|ctx: &mut Capsule<'_>, this: &Vec<Variant>| {
+                let mut items = this.clone();
+                let item = items.pop().ok_or_else(|| Error::value("empty vec"))?;
+                let rest = ctx.environment.boxed(Variant::Vec(items));
+                let item = ctx.environment.boxed(item);
+                let record: Record = vec![(symbol!("0"), rest), (symbol!("1"), item)]
+                    .into_iter()
+                    .collect();
+                Ok(Variant::Record(record))
+            })),
+        );
+        m.insert(
+            "slice".into(),
+            Box::new(NativeMethod::from(
+                |_: &mut Capsule<'_>, this: &Vec<Variant>, start: usize, end: usize| {
+                    check_range(start, end, this.len())?;
+                    Ok(this[start..end].to_vec())
+                },
+            )),
+        );
+        for op in &["+", "concat"] {
+            m.insert(
+                (*op).into(),
+                Box::new(NativeMethod::from(
+                    |ctx: &mut Capsule<'_>, this: &Vec<Variant>, other: Variant| match other {
+                        Variant::Vec(other) => Ok(this.iter().chain(&other).cloned().collect::<Vec<_>>()),
+                        other => Err(Error::invalid_type(symbol!("vec"), other.typename(ctx))),
+                    },
+                )),
+            );
+        }
+        m.insert(
+            "map".into(),
+            Box::new(NativeMethod::from(
+                |ctx: &mut Capsule<'_>, this: &Vec<Variant>, f: Variant| {
+                    this.iter()
+                        .map(|item| apply(ctx, &f, vec![item.clone()]))
+                        .collect::<Fallible<Vec<_>>>()
+                },
+            )),
+        );
+        m.insert(
+            "filter".into(),
+            Box::new(NativeMethod::from(
+                |ctx: &mut Capsule<'_>, this: &Vec<Variant>, f: Variant| {
+                    let mut items = Vec::new();
+                    for item in this {
+                        let keep = apply(ctx, &f, vec![item.clone()])?;
+                        if bool::from_naru(keep, ctx)? {
+                            items.push(item.clone());
+                        }
+                    }
+                    Ok(items)
+                },
+            )),
+        );
+        m.insert(
+            "fold".into(),
+            Box::new(NativeMethod::from(
+                |ctx: &mut Capsule<'_>, this: &Vec<Variant>, init: Variant, f: Variant| {
+                    this.iter()
+                        .try_fold(init, |acc, item| apply(ctx, &f, vec![acc, item.clone()]))
+                },
+            )),
+        );
+        m.insert(
+            "println".into(),
+            Box::new(NativeMethod::from(|ctx: &mut Capsule<'_>, this: &Vec<Variant>| {
+                let text = Variant::Vec(this.clone()).display(ctx, false, &mut Vec::new());
+                ctx.print(format_args!("{}\n", text))
+            })),
+        );
+        m
+    };
+    static ref VTABLE_RANGE: VirtualTable<Range> = {
+        let mut m = VirtualTable::<Range>::new();
+        m.insert(
+            "len".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &Range| {
+                Ok((&this.end - &this.start).to_biguint().unwrap_or_default())
+            })),
+        );
+        m.insert(
+            "contains".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &Range, x: Int| {
+                Ok(this.start <= x && x < this.end)
+            })),
+        );
+        m.insert(
+            "to_vec".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &Range| {
+                let mut items = Vec::new();
+                let mut i = this.start.clone();
+                while i < this.end {
+                    items.push(Variant::Int(i.clone()));
+                    i += 1;
+                }
+                Ok(items)
+            })),
+        );
+        m
+    };
     static ref VTABLE_STR: VirtualTable<String> = {
         let mut m = VirtualTable::<String>::new();
+        insert_comparisons!(m, String);
+        for op in &["+", "concat"] {
+            m.insert(
+                (*op).into(),
+                Box::new(NativeMethod::from(
+                    |_: &mut Capsule<'_>, this: &String, other: String| Ok(this.clone() + &other),
+                )),
+            );
+        }
+        m.insert(
+            "len".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &String| {
+                Ok(Nat::from(this.chars().count()))
+            })),
+        );
+        m.insert(
+            "is_empty".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &String| {
+                Ok(this.is_empty())
+            })),
+        );
+        m.insert(
+            "substring".into(),
+            Box::new(NativeMethod::from(
+                |_: &mut Capsule<'_>, this: &String, start: usize, end: usize| {
+                    substring(this, start, end)
+                },
+            )),
+        );
+        m.insert(
+            "chars".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &String| {
+                let chars = this.chars().map(|c| Variant::from(c.to_string()));
+                Ok(chars.collect::<Vec<_>>())
+            })),
+        );
+        m.insert(
+            "split".into(),
+            Box::new(NativeMethod::from(
+                |_: &mut Capsule<'_>, this: &String, sep: String| {
+                    if sep.is_empty() {
+                        return Err(Error::value("empty separator"));
+                    }
+                    let parts = this.split(sep.as_str()).map(Variant::from);
+                    Ok(parts.collect::<Vec<_>>())
+                },
+            )),
+        );
+        m.insert(
+            "trim".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &String| {
+                Ok(this.trim().to_string())
+            })),
+        );
+        m.insert(
+            "starts_with".into(),
+            Box::new(NativeMethod::from(
+                |_: &mut Capsule<'_>, this: &String, prefix: String| Ok(this.starts_with(&prefix)),
+            )),
+        );
+        m.insert(
+            "ends_with".into(),
+            Box::new(NativeMethod::from(
+                |_: &mut Capsule<'_>, this: &String, suffix: String| Ok(this.ends_with(&suffix)),
+            )),
+        );
+        m.insert(
+            "contains".into(),
+            Box::new(NativeMethod::from(
+                |_: &mut Capsule<'_>, this: &String, needle: String| Ok(this.contains(&needle)),
+            )),
+        );
+        m.insert(
+            "replace".into(),
+            Box::new(NativeMethod::from(
+                |_: &mut Capsule<'_>, this: &String, from: String, to: String| {
+                    if from.is_empty() {
+                        return Err(Error::value("empty pattern"));
+                    }
+                    Ok(this.replace(&from, &to))
+                },
+            )),
+        );
+        m.insert(
+            "to_upper".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &String| {
+                Ok(this.to_uppercase())
+            })),
+        );
+        m.insert(
+            "to_lower".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &String| {
+                Ok(this.to_lowercase())
+            })),
+        );
+        m.insert(
+            "to_int".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &String| {
+                parse_number::<Int>(this, "int")
+            })),
+        );
+        m.insert(
+            "to_nat".into(),
+            Box::new(NativeMethod::from(|_: &mut Capsule<'_>, this: &String| {
+                parse_number::<Nat>(this, "nat")
+            })),
+        );
         m.insert(
             "println".into(),
             Box::new(NativeMethod::from(
@@ -151,6 +1097,36 @@ impl From<Nat> for Variant {
     }
 }
 
+impl From<Rat> for Variant {
+    fn from(val: Rat) -> Self {
+        Variant::Rat(Box::new(val))
+    }
+}
+
+impl From<Record> for Variant {
+    fn from(val: Record) -> Self {
+        Variant::Record(val)
+    }
+}
+
+impl From<Vec<Variant>> for Variant {
+    fn from(val: Vec<Variant>) -> Self {
+        Variant::Vec(val)
+    }
+}
+
+impl From<Range> for Variant {
+    fn from(val: Range) -> Self {
+        Variant::Range(Box::new(val))
+    }
+}
+
+impl From<String> for Variant {
+    fn from(val: String) -> Self {
+        Variant::Str(val)
+    }
+}
+
 impl From<&str> for Variant {
     fn from(val: &str) -> Self {
         Variant::Str(val.into())
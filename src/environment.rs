@@ -1,10 +1,13 @@
+use std::collections::HashSet;
+use std::ops::Range;
+use std::path::Path;
 use std::sync::Arc;
 
 use urashima_util::arena::{Arena, Index};
 
 use crate::{
     data::{Function, Symbol, Variant},
-    error::{Error, Fallible},
+    error::{Error, Fallible, Frame},
 };
 
 /// Execution context
@@ -12,7 +15,13 @@ use crate::{
 pub struct Environment {
     pub(crate) values: Vec<Variant>,
     pub(crate) names: Vec<Symbol>,
-    heads: Vec<usize>, // TODO: call stack metadata
+    heads: Vec<usize>,
+    /// Captured bindings of the functions being called, with the number of bindings
+    /// at the time each call began
+    scopes: Vec<(Arc<Scope>, usize)>,
+    frames: Vec<Frame>,
+    /// Path of the source file being evaluated
+    path: Option<Arc<Path>>,
     packages: Vec<Arc<Package>>,
     fn_arena: Arena<Function>,
     arena: Arena<Variant>,
@@ -24,13 +33,39 @@ impl Environment {
         self.values.push(value);
     }
 
+    /// Inside a function, the names are looked up in its own bindings, the captured ones, and
+    /// then the top-level bindings which were visible when the function was created. Names
+    /// bound at the top level afterwards are looked up last, so that functions can refer to
+    /// the ones defined after them.
     pub(crate) fn lookup_name(&self, name: &str) -> Fallible<&Variant> {
-        let i = self
-            .names
-            .iter()
-            .rposition(|n| n == name)
-            .ok_or_else(|| Error::name(name))?;
-        Ok(&self.values[i])
+        let found = match self.scopes.last() {
+            None => self.find(name, 0..self.names.len()),
+            Some((scope, base)) => {
+                let globals = self.globals();
+                let visible = scope.globals.min(globals);
+                self.find(name, *base..self.names.len())
+                    .or_else(|| scope.find(name))
+                    .or_else(|| self.find(name, 0..visible))
+                    .or_else(|| self.find(name, visible..globals))
+            }
+        };
+        found.ok_or_else(|| Error::name(name))
+    }
+
+    fn find(&self, name: &str, range: Range<usize>) -> Option<&Variant> {
+        let start = range.start;
+        let i = self.names[range].iter().rposition(|n| n == name)?;
+        Some(&self.values[start + i])
+    }
+
+    /// Number of the top-level bindings, which are not in any block nor function call
+    fn globals(&self) -> usize {
+        let head = self.heads.first().copied();
+        let call = self.scopes.first().map(|(_, base)| *base);
+        head.into_iter()
+            .chain(call)
+            .min()
+            .unwrap_or(self.names.len())
     }
 
     pub(crate) fn add_package(&mut self, pkg: Arc<Package>) {
@@ -43,10 +78,115 @@ impl Environment {
 
     pub(crate) fn pop(&mut self) {
         if let Some(head) = self.heads.pop() {
+            self.names.truncate(head);
             self.values.truncate(head);
         }
     }
 
+    /// The call site is in the code being evaluated, so the frame is located in its source file.
+    pub(crate) fn push_frame(&mut self, mut frame: Frame) {
+        if frame.path.is_none() {
+            frame.path = self.path().cloned();
+        }
+        self.frames.push(frame);
+    }
+
+    pub(crate) fn pop_frame(&mut self) {
+        self.frames.pop();
+    }
+
+    pub(crate) fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub(crate) fn set_path(&mut self, path: &Path) {
+        self.path = Some(Arc::from(path));
+    }
+
+    /// Path of the source file of the function being called, or of the program being evaluated
+    pub(crate) fn path(&self) -> Option<&Arc<Path>> {
+        match self.scopes.last() {
+            Some((scope, _)) => scope.path.as_ref(),
+            None => self.path.as_ref(),
+        }
+    }
+
+    /// Take a snapshot of the local bindings currently visible. Top-level bindings are not
+    /// captured, because they are looked up when the function is called.
+    pub(crate) fn capture(&self) -> Scope {
+        let (mut scope, base) = match self.scopes.last() {
+            Some((scope, base)) => (Scope::clone(scope), *base),
+            None => {
+                let globals = self.globals();
+                let scope = Scope {
+                    globals,
+                    path: self.path.clone(),
+                    ..Scope::default()
+                };
+                (scope, globals)
+            }
+        };
+        scope.names.extend_from_slice(&self.names[base..]);
+        scope.values.extend_from_slice(&self.values[base..]);
+        scope
+    }
+
+    /// Make the captured bindings visible in place of the ones of the caller,
+    /// until [`Environment::leave`] is called.
+    pub(crate) fn enter(&mut self, scope: &Arc<Scope>) {
+        self.scopes.push((Arc::clone(scope), self.names.len()));
+    }
+
+    pub(crate) fn leave(&mut self) {
+        if let Some((_, base)) = self.scopes.pop() {
+            self.names.truncate(base);
+            self.values.truncate(base);
+        }
+    }
+
+    /// Whether no function call nor block is being evaluated, so that every live value is
+    /// reachable from the bindings.
+    pub(crate) fn is_top_level(&self) -> bool {
+        self.heads.is_empty() && self.scopes.is_empty() && self.frames.is_empty()
+    }
+
+    /// Number of values and functions in the arenas
+    pub(crate) fn allocated(&self) -> usize {
+        self.arena.len() + self.fn_arena.len()
+    }
+
+    /// Free the values and the functions which are not reachable from the bindings, and
+    /// returns how many are freed. This should be called only at the top level, because
+    /// values held by the evaluator are not taken into account.
+    pub(crate) fn collect_garbage(&mut self) -> usize {
+        let mut values = HashSet::new();
+        let mut functions = HashSet::new();
+        let mut pending: Vec<&Variant> = self.values.iter().collect();
+        while let Some(value) = pending.pop() {
+            match value {
+                Variant::Record(record) => {
+                    for (_, idx) in record.iter() {
+                        if values.insert(idx) {
+                            pending.extend(self.arena.get(idx));
+                        }
+                    }
+                }
+                Variant::Vec(items) => pending.extend(items),
+                Variant::Ref(idx) if values.insert(*idx) => {
+                    pending.extend(self.arena.get(*idx));
+                }
+                Variant::Fn(idx) if functions.insert(*idx) => {
+                    if let Some(f) = self.fn_arena.get(*idx) {
+                        pending.extend(f.environment().values.iter());
+                    }
+                }
+                _ => {}
+            }
+        }
+        let freed = self.arena.retain(|idx, _| values.contains(&idx));
+        freed + self.fn_arena.retain(|idx, _| functions.contains(&idx))
+    }
+
     pub(crate) fn boxed(&mut self, value: Variant) -> Index<Variant> {
         self.arena.insert(value)
     }
@@ -55,6 +195,10 @@ impl Environment {
         self.arena.get(idx)
     }
 
+    pub(crate) fn get_mut(&mut self, idx: Index<Variant>) -> Option<&mut Variant> {
+        self.arena.get_mut(idx)
+    }
+
     pub(crate) fn add_function(&mut self, f: Function) -> Index<Function> {
         self.fn_arena.insert(f)
     }
@@ -62,6 +206,33 @@ impl Environment {
     pub(crate) fn get_function(&self, idx: Index<Function>) -> Option<&Function> {
         self.fn_arena.get(idx)
     }
+
+    pub(crate) fn get_function_mut(&mut self, idx: Index<Function>) -> Option<&mut Function> {
+        self.fn_arena.get_mut(idx)
+    }
+}
+
+/// Bindings captured by a closure
+#[derive(Clone, Default)]
+pub struct Scope {
+    names: Vec<Symbol>,
+    values: Vec<Variant>,
+    /// Number of the top-level bindings visible when the bindings are captured
+    globals: usize,
+    /// Path of the source file where the function is defined
+    path: Option<Arc<Path>>,
+}
+
+impl Scope {
+    pub(crate) fn bind(&mut self, name: &str, value: Variant) {
+        self.names.push(name.into());
+        self.values.push(value);
+    }
+
+    fn find(&self, name: &str) -> Option<&Variant> {
+        let i = self.names.iter().rposition(|n| n == name)?;
+        Some(&self.values[i])
+    }
 }
 
 pub struct Package {
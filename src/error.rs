@@ -1,15 +1,21 @@
 use std::borrow::Cow;
 use std::fmt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use failure::{Backtrace, Context, Fail};
+use urashima_ast::{
+    expr::{impls::Expression, CallExpression, ExprArena, InvokeExpression},
+    span::Span,
+};
 use urashima_util::PackagePath;
 
-use crate::data::{symbol, Symbol};
+use crate::data::{symbol, Symbol, Variant};
 
 #[derive(Debug)]
 pub struct Error {
     inner: Context<ErrorKind>,
+    traceback: Option<Box<Traceback>>,
 }
 
 impl Fail for Error {
@@ -41,17 +47,30 @@ impl Error {
         ErrorKind::Runtime.into()
     }
 
-    pub(crate) fn unimplemented() -> Error {
-        ErrorKind::Unimplemented.into()
-    }
-
     pub(crate) fn name(name: impl Into<Symbol>) -> Error {
         ErrorKind::Name { name: name.into() }.into()
     }
 
-    pub(crate) fn invalid_type(expected: impl Into<Symbol>) -> Error {
+    pub(crate) fn no_method(typename: impl Into<Symbol>, method: impl Into<Symbol>) -> Error {
+        ErrorKind::Method {
+            typename: typename.into(),
+            method: method.into(),
+        }
+        .into()
+    }
+
+    pub(crate) fn no_field(typename: impl Into<Symbol>, label: impl Into<Symbol>) -> Error {
+        ErrorKind::Field {
+            typename: typename.into(),
+            label: label.into(),
+        }
+        .into()
+    }
+
+    pub(crate) fn invalid_type(expected: impl Into<Symbol>, found: impl Into<Symbol>) -> Error {
         ErrorKind::Type {
             expected: expected.into(),
+            found: found.into(),
         }
         .into()
     }
@@ -63,6 +82,17 @@ impl Error {
         .into()
     }
 
+    pub(crate) fn no_match(value: impl Into<String>) -> Error {
+        ErrorKind::Match {
+            value: value.into(),
+        }
+        .into()
+    }
+
+    pub(crate) fn assertion(failure: AssertionFailure) -> Error {
+        ErrorKind::Assertion(failure).into()
+    }
+
     pub(crate) fn import(path: &PackagePath) -> Error {
         ErrorKind::Import(path.clone()).into()
     }
@@ -79,6 +109,83 @@ impl Error {
         ErrorKind::ControlFlow(ControlFlow::Continue).into()
     }
 
+    pub(crate) fn fn_return(value: Variant) -> Error {
+        ErrorKind::ControlFlow(ControlFlow::Return(value)).into()
+    }
+
+    /// Location where the error was raised, and the call stack of the Naru program at that point
+    pub fn traceback(&self) -> Option<&Traceback> {
+        self.traceback.as_deref()
+    }
+
+    /// Location of the source code where the error was raised
+    pub fn span(&self) -> Option<Span> {
+        self.traceback.as_ref()?.span
+    }
+
+    /// Path of the source file which the error refers to
+    pub fn path(&self) -> Option<&Path> {
+        self.traceback.as_ref()?.path()
+    }
+
+    /// Render the error with the lines of the source code where it was raised.
+    pub fn report<'a>(&'a self, source: &'a str) -> Report<'a> {
+        Report {
+            error: self,
+            source,
+        }
+    }
+
+    fn traceback_mut(&mut self) -> Option<&mut Traceback> {
+        if self.as_control_flow().is_some() {
+            return None;
+        }
+        Some(self.traceback.get_or_insert_with(Default::default))
+    }
+
+    /// Record the location of the expression, unless an inner expression was already recorded.
+    /// The path is of the source file which contains the expression, if it is known.
+    pub(crate) fn with_span(mut self, span: Span, path: Option<&Arc<Path>>) -> Self {
+        if let Some(traceback) = self.traceback_mut() {
+            if traceback.span.is_none() {
+                traceback.span = Some(span);
+                traceback.path = path.map(|p| p.to_path_buf());
+            }
+        }
+        self
+    }
+
+    /// Record the call stack, unless it was already recorded by an inner call.
+    pub(crate) fn with_frames(mut self, frames: &[Frame]) -> Self {
+        if let Some(traceback) = self.traceback_mut() {
+            if traceback.frames.is_empty() {
+                traceback.frames = frames.to_vec();
+            }
+        }
+        self
+    }
+
+    /// Record the path of the source file which the error refers to, and of the calls whose
+    /// paths are unknown.
+    pub(crate) fn with_path(mut self, path: &Path) -> Self {
+        if let Some(traceback) = self.traceback_mut() {
+            traceback.path.get_or_insert_with(|| path.to_owned());
+            for frame in &mut traceback.frames {
+                frame.path.get_or_insert_with(|| Arc::from(path));
+            }
+        }
+        self
+    }
+
+    /// Details of the failed `assert` statement, if the error was raised by one
+    pub fn as_assertion(&self) -> Option<&AssertionFailure> {
+        if let ErrorKind::Assertion(failure) = self.inner.get_context() {
+            Some(failure)
+        } else {
+            None
+        }
+    }
+
     pub(crate) fn as_control_flow(&self) -> Option<&ControlFlow> {
         if let ErrorKind::ControlFlow(cf) = self.inner.get_context() {
             Some(cf)
@@ -90,24 +197,37 @@ impl Error {
 
 #[derive(Debug, Fail)]
 enum ErrorKind {
+    #[cfg(feature = "deserialize")]
     #[fail(display = "parse error: {}", _0)]
     Parse(String),
 
+    #[fail(display = "{}", _0)]
+    Syntax(#[fail(cause)] urashima_ast::error::Error),
+
     #[fail(display = "runtime error")]
     Runtime,
 
-    #[fail(display = "unimplemented")]
-    Unimplemented,
-
     #[fail(display = "name error: {}", name)]
     Name { name: Symbol },
 
-    #[fail(display = "type error: expected '{}'", expected)]
-    Type { expected: Symbol },
+    #[fail(display = "name error: {} has no method '{}'", typename, method)]
+    Method { typename: Symbol, method: Symbol },
+
+    #[fail(display = "name error: {} has no field '{}'", typename, label)]
+    Field { typename: Symbol, label: Symbol },
+
+    #[fail(display = "type error: expected '{}', found '{}'", expected, found)]
+    Type { expected: Symbol, found: Symbol },
 
     #[fail(display = "value error: {}", reason)]
     Value { reason: Cow<'static, str> },
 
+    #[fail(display = "match error: no switch arm matches {}", value)]
+    Match { value: String },
+
+    #[fail(display = "{}", _0)]
+    Assertion(AssertionFailure),
+
     #[fail(display = "import error")]
     Import(PackagePath),
 
@@ -122,28 +242,205 @@ impl From<ErrorKind> for Error {
     fn from(kind: ErrorKind) -> Self {
         Error {
             inner: Context::new(kind),
+            traceback: None,
         }
     }
 }
 
 impl From<Context<ErrorKind>> for Error {
     fn from(inner: Context<ErrorKind>) -> Self {
-        Error { inner }
+        Error {
+            inner,
+            traceback: None,
+        }
     }
 }
 
 impl From<urashima_ast::error::Error> for Error {
     fn from(err: urashima_ast::error::Error) -> Self {
-        ErrorKind::Parse(err.to_string()).into()
+        let span = err.span();
+        let err: Error = ErrorKind::Syntax(err).into();
+        match span {
+            Some(span) => err.with_span(span, None),
+            None => err,
+        }
     }
 }
 
 pub type Fallible<T> = Result<T, Error>;
 
-#[derive(Debug)]
+/// A failed `assert` statement
+#[derive(Clone, Debug)]
+pub struct AssertionFailure {
+    /// Source text of the asserted expression
+    pub expr: String,
+    /// Left and right values of the expression, if it is a comparison
+    pub operands: Option<(String, String)>,
+    pub message: Option<String>,
+}
+
+impl fmt::Display for AssertionFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "assertion failed: {}", self.expr)?;
+        if let Some((left, right)) = &self.operands {
+            write!(f, " (left: {}, right: {})", left, right)?;
+        }
+        if let Some(message) = &self.message {
+            write!(f, ": {}", message)?;
+        }
+        Ok(())
+    }
+}
+
+/// A function call in progress
+#[derive(Clone, Debug)]
+pub struct Frame {
+    /// Name of the function or the method being called
+    pub name: Symbol,
+    /// Location of the call site
+    pub span: Span,
+    /// Path of the source file which contains the call site
+    pub path: Option<Arc<Path>>,
+}
+
+impl Frame {
+    pub(crate) fn call(arena: &ExprArena, expr: &CallExpression) -> Frame {
+        let callee = &arena[expr.callee];
+        let name = match &callee.node {
+            Expression::Name(name) => name.clone(),
+            _ => symbol!("fn"),
+        };
+        Frame {
+            name,
+            span: Span::enclosing(callee.span, expr.arguments.span),
+            path: None,
+        }
+    }
+
+    pub(crate) fn invoke(expr: &InvokeExpression) -> Frame {
+        Frame {
+            name: expr.method.node.clone(),
+            span: Span::enclosing(expr.method.span, expr.arguments.span),
+            path: None,
+        }
+    }
+}
+
+/// Naru-level backtrace, which consists of the location where an error was raised and
+/// the calls leading to it
+#[derive(Clone, Debug, Default)]
+pub struct Traceback {
+    path: Option<PathBuf>,
+    span: Option<Span>,
+    frames: Vec<Frame>,
+}
+
+impl Traceback {
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+
+    /// Frames from the innermost call to the outermost one
+    pub fn frames(&self) -> impl Iterator<Item = &Frame> {
+        self.frames.iter().rev()
+    }
+
+    fn display_path(&self) -> Cow<'_, str> {
+        match &self.path {
+            Some(path) => path.to_string_lossy(),
+            None => "<input>".into(),
+        }
+    }
+}
+
+impl fmt::Display for Traceback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for frame in self.frames() {
+            let path = match &frame.path {
+                Some(path) => path.to_string_lossy(),
+                None => self.display_path(),
+            };
+            let pos = frame.span.start();
+            writeln!(
+                f,
+                "  at {} ({}:{}:{})",
+                frame.name, path, pos.line, pos.column
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// An error rendered with the source lines it refers to, underlined with carets:
+///
+/// ```text
+/// name error: y
+///  --> example.n:2:9
+///   |
+/// 2 |     x + y
+///   |         ^
+///   at inner (example.n:5:5)
+/// ```
+pub struct Report<'a> {
+    error: &'a Error,
+    source: &'a str,
+}
+
+impl fmt::Display for Report<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.error)?;
+        let traceback = match self.error.traceback() {
+            Some(traceback) => traceback,
+            None => return Ok(()),
+        };
+        if let Some(span) = traceback.span {
+            let (start, end) = (span.start(), span.end());
+            let width = end.line.to_string().len();
+            writeln!(
+                f,
+                "{:width$}--> {}:{}:{}",
+                "",
+                traceback.display_path(),
+                start.line,
+                start.column,
+                width = width
+            )?;
+            writeln!(f, "{:width$} |", "", width = width)?;
+            let lines = self.source.lines().enumerate().map(|(i, l)| (i + 1, l));
+            for (n, line) in lines
+                .skip(start.line.saturating_sub(1))
+                .take(end.line - start.line + 1)
+            {
+                let line = line.trim_end_matches('\r');
+                let from = if n == start.line { start.column } else { 1 };
+                let to = if n == end.line {
+                    end.column
+                } else {
+                    line.chars().count() + 1
+                };
+                // Keep tabs in the indentation, so that the carets are aligned with the line.
+                let indent: String = line
+                    .chars()
+                    .take(from - 1)
+                    .map(|c| if c == '\t' { '\t' } else { ' ' })
+                    .collect();
+                let carets = "^".repeat(to.saturating_sub(from).max(1));
+                writeln!(f, "{:>width$} | {}", n, line, width = width)?;
+                writeln!(f, "{:width$} | {}{}", "", indent, carets, width = width)?;
+            }
+        }
+        write!(f, "{}", traceback)
+    }
+}
+
 pub enum ControlFlow {
     Break,
     Continue,
+    Return(Variant),
 }
 
 impl ControlFlow {
@@ -151,6 +448,17 @@ impl ControlFlow {
         match self {
             ControlFlow::Break => symbol!("break"),
             ControlFlow::Continue => symbol!("continue"),
+            ControlFlow::Return(_) => symbol!("return"),
+        }
+    }
+}
+
+impl fmt::Debug for ControlFlow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControlFlow::Break => f.write_str("Break"),
+            ControlFlow::Continue => f.write_str("Continue"),
+            ControlFlow::Return(_) => f.write_str("Return(..)"),
         }
     }
 }
@@ -1,7 +1,9 @@
 use urashima_ast::{
     expr::{
-        block::BlockExpression, impls::Expression, CallExpression, ExprIndex, FunctionExpression,
-        IfExpression, InvokeExpression, LoopExpression,
+        block::BlockExpression,
+        impls::{Expression, FormatPart},
+        CallExpression, ExprIndex, ForExpression, FunctionExpression, IfExpression,
+        InvokeExpression, LoopExpression, SwitchExpression,
     },
     span::Spanned,
 };
@@ -9,8 +11,8 @@ use urashima_ast::{
 use super::Evaluate;
 use crate::{
     capsule::Capsule,
-    data::{symbol, Function, Symbol, Variant},
-    error::{ControlFlow, Error, Fallible},
+    data::{iter::Iter, pattern::match_pattern, symbol, Function, Symbol, Variant},
+    error::{ControlFlow, Error, Fallible, Frame},
 };
 
 impl<T> Evaluate for Spanned<T>
@@ -20,7 +22,9 @@ where
     type Value = T::Value;
 
     fn eval(&self, ctx: &mut Capsule<'_>) -> Fallible<Self::Value> {
-        self.node.eval(ctx)
+        self.node
+            .eval(ctx)
+            .map_err(|e| e.with_span(self.span, ctx.environment.path()))
     }
 }
 
@@ -45,32 +49,52 @@ impl Evaluate for Expression {
         match self {
             False => Ok(Variant::Bool(false)),
             True => Ok(Variant::Bool(true)),
-            Integral(val) => Ok(Variant::Int((*val).into())),
+            Integral(val) => Ok(Variant::Int(val.clone())),
+            Rational(val) => Ok(Variant::from(val.clone())),
             Str(val) => Ok(Variant::from(&val[..])),
             Name(name) => ctx.environment.lookup_name(name).map(Clone::clone),
             Record(exprs) => eval_record(ctx, &exprs),
+            Format(parts) => {
+                let mut buf = String::new();
+                for part in parts {
+                    match part {
+                        FormatPart::Str(s) => buf.push_str(s),
+                        FormatPart::Expr(expr) => buf.push_str(&expr.eval(ctx)?.to_str(ctx)?),
+                    }
+                }
+                Ok(Variant::Str(buf))
+            }
             Block(blk) => blk.eval(ctx),
             Fn(expr) => expr.eval(ctx),
 
             Infix(op, a, b) => {
                 let a = a.eval(ctx)?;
                 let b = b.eval(ctx)?;
-                match (op.as_ref(), a, b) {
-                    ("+", Variant::Int(a), Variant::Int(b)) => Ok(Variant::Int(a + b)),
-                    ("-", Variant::Int(a), Variant::Int(b)) => Ok(Variant::Int(a - b)),
-                    ("*", Variant::Int(a), Variant::Int(b)) => Ok(Variant::Int(a * b)),
-                    ("/", Variant::Int(a), Variant::Int(b)) => Ok(Variant::Int(a / b)),
-                    _ => Err(Error::unimplemented()),
-                }
+                a.invoke(ctx, op.node.clone(), &[b])
             }
             New(expr) => {
                 let val = expr.eval(ctx)?;
                 Ok(Variant::Ref(ctx.environment.boxed(val)))
             }
+            Field(expr, label) => expr.eval(ctx)?.field(ctx, &label.node),
+            Index(expr, index) => {
+                let value = expr.eval(ctx)?;
+                let index = index.eval(ctx)?;
+                value.index(ctx, index)
+            }
+            Vec(items) => {
+                let items = items
+                    .iter()
+                    .map(|item| item.eval(ctx))
+                    .collect::<Fallible<_>>()?;
+                Ok(Variant::Vec(items))
+            }
             Call(expr) => expr.eval(ctx),
             Invoke(expr) => expr.eval(ctx),
             If(expr) => expr.eval(ctx),
             Loop(expr) => expr.eval(ctx),
+            For(expr) => expr.eval(ctx),
+            Switch(expr) => expr.eval(ctx),
         }
     }
 }
@@ -79,16 +103,13 @@ impl Evaluate for IfExpression {
     type Value = Variant;
 
     fn eval(&self, ctx: &mut Capsule<'_>) -> Fallible<Self::Value> {
-        if let Variant::Bool(c) = self.cond.eval(ctx)? {
-            if c {
-                self.then_blk.eval(&mut ctx.push())
-            } else if let Some(e) = &self.else_blk {
-                e.eval(&mut ctx.push())
-            } else {
-                Ok(Variant::unit())
-            }
-        } else {
-            Err(Error::invalid_type(symbol!("bool")))
+        match self.cond.eval(ctx)? {
+            Variant::Bool(true) => self.then_blk.eval(&mut ctx.push()),
+            Variant::Bool(false) => match &self.else_blk {
+                Some(e) => e.eval(&mut ctx.push()),
+                None => Ok(Variant::unit()),
+            },
+            cond => Err(Error::invalid_type(symbol!("bool"), cond.typename(ctx))),
         }
     }
 }
@@ -102,7 +123,7 @@ impl Evaluate for LoopExpression {
                 match e.as_control_flow() {
                     Some(ControlFlow::Break) => break,
                     Some(ControlFlow::Continue) => continue,
-                    None => {
+                    Some(ControlFlow::Return(_)) | None => {
                         return Err(e);
                     }
                 }
@@ -112,16 +133,85 @@ impl Evaluate for LoopExpression {
     }
 }
 
+impl Evaluate for ForExpression {
+    type Value = Variant;
+
+    fn eval(&self, ctx: &mut Capsule<'_>) -> Fallible<Self::Value> {
+        let iterable = self.iterable.eval(ctx)?;
+        let mut iter = Iter::new(ctx, iterable)?;
+        while let Some(item) = iter.next(ctx)? {
+            let mut ctx = ctx.push();
+            ctx.bind(&self.binding.node, item);
+            if let Err(e) = self.blk.eval(&mut ctx) {
+                match e.as_control_flow() {
+                    Some(ControlFlow::Break) => break,
+                    Some(ControlFlow::Continue) => continue,
+                    Some(ControlFlow::Return(_)) | None => {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        Ok(Variant::unit())
+    }
+}
+
+impl Evaluate for SwitchExpression {
+    type Value = Variant;
+
+    fn eval(&self, ctx: &mut Capsule<'_>) -> Fallible<Self::Value> {
+        let subject = self.subject.eval(ctx)?;
+        for arm in &self.arms {
+            let pattern = &arm.pattern.node;
+            let mut scope: Vec<_> = (pattern.bindings().into_iter())
+                .map(|name| match ctx.environment.lookup_name(name) {
+                    Ok(value) => value.clone(),
+                    Err(_) => Variant::unit(),
+                })
+                .collect();
+            for tag in pattern.tags() {
+                scope.push(ctx.environment.lookup_name(tag)?.clone());
+            }
+            let bound = match match_pattern(ctx, pattern, &subject, &scope) {
+                Some(bound) => bound,
+                None => continue,
+            };
+            let mut ctx = ctx.push();
+            for (name, value) in pattern.bindings().into_iter().zip(bound) {
+                ctx.bind(name, value);
+            }
+            if let Some(guard) = arm.guard {
+                match guard.eval(&mut ctx)? {
+                    Variant::Bool(true) => {}
+                    Variant::Bool(false) => continue,
+                    cond => {
+                        let found = cond.typename(&mut ctx);
+                        return Err(Error::invalid_type(symbol!("bool"), found));
+                    }
+                }
+            }
+            return arm.body.eval(&mut ctx);
+        }
+        Err(Error::no_match(subject.repr(ctx)))
+    }
+}
+
 impl Evaluate for CallExpression {
     type Value = Variant;
 
     fn eval(&self, ctx: &mut Capsule<'_>) -> Fallible<Self::Value> {
         let callee = self.callee.eval(ctx)?;
-        let f = callee
-            .as_function(&ctx)
-            .ok_or_else(|| Error::invalid_type(symbol!("fn")))?
-            .clone();
-        f.call(ctx, &self.arguments)
+        let f = match callee.as_function(&ctx) {
+            Some(f) => f.clone(),
+            None => return Err(Error::invalid_type(symbol!("fn"), callee.typename(ctx))),
+        };
+        let arguments = self
+            .arguments
+            .iter()
+            .map(|i| i.eval(ctx))
+            .collect::<Fallible<Vec<_>>>()?;
+        let frame = Frame::call(&ctx.expr_arena, self);
+        ctx.call_frame(frame, |ctx| f.apply(ctx, arguments))
     }
 }
 
@@ -135,7 +225,14 @@ impl Evaluate for InvokeExpression {
             .iter()
             .map(|i| i.eval(ctx))
             .collect::<Fallible<Vec<_>>>()?;
-        receiver.invoke(ctx, self.method.node.clone(), &arguments)
+        let method = self.method.node.clone();
+        if receiver.has_fn_method(ctx, &method) {
+            ctx.call_frame(Frame::invoke(self), |ctx| {
+                receiver.invoke(ctx, method, &arguments)
+            })
+        } else {
+            receiver.invoke(ctx, method, &arguments)
+        }
     }
 }
 
@@ -182,13 +279,223 @@ impl Evaluate for FunctionExpression {
             ctx,
             self.parameters.iter().map(|i| i.name()).collect(),
             self.body.node.clone(),
-            // ctx.environment.clone(),
         );
         let idx = ctx.environment.add_function(f);
         Ok(Variant::Fn(idx))
     }
 }
 
+#[cfg(test)]
+mod test_expr_cmp {
+    use super::*;
+    use crate::runtime::Runtime;
+
+    fn eval_bool(code: &str) -> Fallible<bool> {
+        let rt = Runtime::new();
+        let mut capsule = rt.root_capsule();
+        let expr: ExprIndex = capsule.parse_sourcecode(code)?;
+        let value = capsule.eval(&expr)?;
+        Ok(value.to_bool().expect("bool"))
+    }
+
+    #[test]
+    fn compare_int() {
+        assert!(eval_bool("1 < 2").unwrap());
+        assert!(!eval_bool("2 < 1").unwrap());
+        assert!(eval_bool("2 > 1").unwrap());
+        assert!(eval_bool("2 <= 2").unwrap());
+        assert!(eval_bool("2 >= 3 - 1").unwrap());
+        assert!(eval_bool("1 + 1 == 2").unwrap());
+        assert!(eval_bool("1 != 2").unwrap());
+    }
+
+    #[test]
+    fn compare_str() {
+        assert!(eval_bool("'abc' < 'abd'").unwrap());
+        assert!(eval_bool("'abc' == 'abc'").unwrap());
+        assert!(eval_bool("'abc' != 'ab'").unwrap());
+    }
+
+    #[test]
+    fn compare_bool() {
+        assert!(eval_bool("false < true").unwrap());
+        assert!(eval_bool("true == true").unwrap());
+        assert!(eval_bool("true != false").unwrap());
+    }
+
+    #[test]
+    fn compare_mismatched() {
+        assert!(!eval_bool("1 == 'a'").unwrap());
+        assert!(eval_bool("1 < 'a'").is_err());
+    }
+
+    #[test]
+    fn equal_record() {
+        let rt = Runtime::new();
+        let mut capsule = rt.root_capsule();
+        let one = Variant::Int(1.into());
+        let a = eval_record_values(&mut capsule, vec![("x", one.clone()), ("y", "a".into())]);
+        let b = eval_record_values(&mut capsule, vec![("y", "a".into()), ("x", one.clone())]);
+        let c = eval_record_values(&mut capsule, vec![("x", one)]);
+        assert!(a.equals(&b, &capsule));
+        assert!(!a.equals(&c, &capsule));
+        assert!(Variant::unit().equals(&Variant::unit(), &capsule));
+    }
+
+    fn eval_record_values(capsule: &mut Capsule<'_>, fields: Vec<(&str, Variant)>) -> Variant {
+        let fields: Vec<_> = fields
+            .into_iter()
+            .map(|(label, value)| (Symbol::from(label), capsule.environment.boxed(value)))
+            .collect();
+        Variant::Record(fields.into_iter().collect())
+    }
+
+    #[test]
+    fn quoted_operator() {
+        let rt = Runtime::new();
+        let mut capsule = rt.root_capsule();
+        let expr: ExprIndex = capsule.parse_sourcecode("1 `+`(2)").unwrap();
+        let value = capsule.eval(&expr).unwrap();
+        assert_eq!(value.to_int(), Some(&3.into()));
+    }
+
+    #[test]
+    fn undefined_operator() {
+        let err = eval_bool("1 ++ 2").unwrap_err();
+        assert_eq!(err.to_string(), "name error: int has no method '++'");
+        let err = eval_bool("'a' - 'b'").unwrap_err();
+        assert_eq!(err.to_string(), "name error: str has no method '-'");
+    }
+
+    #[test]
+    fn division_by_zero() {
+        let err = eval_bool("1 / 0").unwrap_err();
+        assert_eq!(err.to_string(), "value error: division by zero");
+    }
+
+    #[test]
+    fn record_operator() {
+        let rt = Runtime::new();
+        let mut capsule = rt.root_capsule();
+        let expr: ExprIndex = capsule
+            .parse_sourcecode("fn (self, other) { other == 42 }")
+            .unwrap();
+        let f = capsule.eval(&expr).unwrap();
+        let a = eval_record_values(&mut capsule, vec![("<+>", f)]);
+        capsule.bind("a", a);
+        assert!(eval_bool_in(&mut capsule, "a <+> 42").unwrap());
+        assert!(!eval_bool_in(&mut capsule, "a <+> 0").unwrap());
+        let err = eval_bool_in(&mut capsule, "a + 1").unwrap_err();
+        assert_eq!(err.to_string(), "name error: (<+>: fn,) has no method '+'");
+    }
+
+    fn eval_bool_in(capsule: &mut Capsule<'_>, code: &str) -> Fallible<bool> {
+        let expr: ExprIndex = capsule.parse_sourcecode(code)?;
+        let value = capsule.eval(&expr)?;
+        Ok(value.to_bool().expect("bool"))
+    }
+}
+
+#[cfg(test)]
+mod test_expr_num {
+    use super::*;
+    use crate::{
+        data::{Int, Nat, Rat},
+        runtime::Runtime,
+    };
+
+    fn eval(code: &str) -> Fallible<Variant> {
+        let rt = Runtime::new();
+        let mut capsule = rt.root_capsule();
+        let expr: ExprIndex = capsule.parse_sourcecode(code)?;
+        capsule.eval(&expr)
+    }
+
+    #[test]
+    fn int_literal() {
+        let int = |code| eval(code).unwrap().to_int().cloned().unwrap();
+        assert_eq!(int("0xff + 0o10 + 0b11"), 266.into());
+        assert_eq!(int("1_000 * 1_000"), 1_000_000.into());
+        let big: Int = "123456789012345678901234567890".parse().unwrap();
+        assert_eq!(int("123_456_789_012_345_678_901_234_567_890"), big);
+    }
+
+    #[test]
+    fn nat_arithmetic() {
+        let nat = |code| eval(code).unwrap().to_nat().cloned();
+        assert_eq!(nat("3 to_nat() + 4 to_nat()"), Some(Nat::from(7u32)));
+        assert_eq!(nat("3 to_nat() - 3 to_nat()"), Some(Nat::from(0u32)));
+        assert_eq!(nat("3 to_nat() * 4 to_nat()"), Some(Nat::from(12u32)));
+        assert_eq!(nat("7 to_nat() / 2 to_nat()"), Some(Nat::from(3u32)));
+        assert_eq!(nat("5 negate() abs()"), Some(Nat::from(5u32)));
+    }
+
+    #[test]
+    fn nat_int_promotion() {
+        let int = |code| eval(code).unwrap().to_int().cloned();
+        assert_eq!(int("3 to_nat() - 5"), Some((-2).into()));
+        assert_eq!(int("3 - 5 to_nat()"), Some((-2).into()));
+        assert_eq!(int("3 to_nat() * 2"), Some(6.into()));
+        assert_eq!(int("3 to_nat() to_int()"), Some(3.into()));
+        let b = |code| eval(code).unwrap().to_bool().unwrap();
+        assert!(b("3 to_nat() == 3"));
+        assert!(b("2 < 3 to_nat()"));
+        assert!(b("3 to_nat() >= 3 to_nat()"));
+    }
+
+    #[test]
+    fn nat_errors() {
+        let err = |code| eval(code).map(|_| ()).unwrap_err().to_string();
+        assert_eq!(
+            err("1 to_nat() - 2 to_nat()"),
+            "value error: nat subtraction underflow"
+        );
+        assert_eq!(
+            err("1 to_nat() / 0 to_nat()"),
+            "value error: division by zero"
+        );
+        assert_eq!(
+            err("1 negate() to_nat()"),
+            "value error: negative int cannot be a nat"
+        );
+        assert_eq!(
+            err("1 to_nat() + 'a'"),
+            "type error: expected 'nat', found 'str'"
+        );
+    }
+
+    #[test]
+    fn rat_arithmetic() {
+        let rat = |code| eval(code).unwrap().to_rat().cloned().unwrap();
+        let r = |n: i32, d: i32| Rat::new(n.into(), d.into());
+        assert_eq!(rat("0.1 + 0.2"), r(3, 10));
+        assert_eq!(rat("1.5 - 2"), r(-1, 2));
+        assert_eq!(rat("2 * 0.25"), r(1, 2));
+        assert_eq!(rat("1 / 3 to_rat()"), r(1, 3));
+        assert_eq!(rat("3 to_nat() + 0.5"), r(7, 2));
+        assert_eq!(rat("2.50"), r(5, 2));
+        let b = |code| eval(code).unwrap().to_bool().unwrap();
+        assert!(b("0.1 + 0.2 == 0.3"));
+        assert!(b("0.5 < 1"));
+        assert!(b("2.0 == 2"));
+        assert!(b("1 / 3 to_rat() * 3 == 1"));
+    }
+
+    #[test]
+    fn rat_conversions() {
+        let int = |code| eval(code).unwrap().to_int().cloned().unwrap();
+        assert_eq!(int("3.75 to_int()"), 3.into());
+        assert_eq!(int("3.75 negate() to_int()"), (-3).into());
+        assert_eq!(int("3.75 negate() floor()"), (-4).into());
+        assert_eq!(int("3.25 ceil()"), 4.into());
+        assert_eq!(int("2.5 round()"), 3.into());
+        assert_eq!(int("0.75 numer()"), 3.into());
+        assert_eq!(int("0.75 denom()"), 4.into());
+        let err = eval("1.5 / 0").map(|_| ()).unwrap_err();
+        assert_eq!(err.to_string(), "value error: division by zero");
+    }
+}
+
 #[cfg(all(feature = "deserialize", test))]
 mod test_expr_atomic {
     use failure::Fallible;
@@ -417,3 +724,205 @@ mod test_expr_op {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test_expr_ref {
+    use super::*;
+    use crate::runtime::Runtime;
+
+    #[test]
+    fn ref_typename() {
+        let rt = Runtime::new();
+        let mut capsule = rt.root_capsule();
+        let mut typename = |code| {
+            let expr: ExprIndex = capsule.parse_sourcecode(code).unwrap();
+            let value = capsule.eval(&expr).unwrap();
+            value.typename(&mut capsule).to_string()
+        };
+        assert_eq!(typename("new 1"), "ref[int]");
+        assert_eq!(typename("new new 'a'"), "ref[ref[str]]");
+        assert_eq!(typename("(new 1) get()"), "int");
+    }
+
+    #[test]
+    fn ref_set() {
+        let rt = Runtime::new();
+        let mut capsule = rt.root_capsule();
+        capsule.eval("r := new 1\nr set('one')").unwrap();
+        let expr: ExprIndex = capsule.parse_sourcecode("r get()").unwrap();
+        match capsule.eval(&expr).unwrap() {
+            Variant::Str(s) => assert_eq!(s, "one"),
+            _ => panic!("expected a str"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_expr_record {
+    use super::*;
+    use crate::runtime::Runtime;
+
+    #[test]
+    fn record_typename() {
+        let rt = Runtime::new();
+        let mut capsule = rt.root_capsule();
+        let mut typename = |code| {
+            let expr: ExprIndex = capsule.parse_sourcecode(code).unwrap();
+            let value = capsule.eval(&expr).unwrap();
+            value.typename(&mut capsule).to_string()
+        };
+        assert_eq!(typename("()"), "()");
+        assert_eq!(typename("(1,)"), "(int,)");
+        assert_eq!(typename("(1, 'a')"), "(int, str)");
+        assert_eq!(typename("(x: 1, y: 0.5)"), "(x: int, y: rat)");
+        assert_eq!(
+            typename("(1, y: (true, new 1))"),
+            "(int, y: (bool, ref[int]))"
+        );
+    }
+
+    #[test]
+    fn record_type() {
+        let rt = Runtime::new();
+        let mut capsule = rt.root_capsule();
+        let expr: ExprIndex = capsule.parse_sourcecode("('a', y: 1)").unwrap();
+        let value = capsule.eval(&expr).unwrap();
+        let ty = value.record_type(&mut capsule).unwrap();
+        assert_eq!(ty.len(), 2);
+        assert_eq!(ty.label(0), None);
+        assert_eq!(ty.label(1).map(|l| &**l), Some("y"));
+        assert_eq!(ty.field_type(0), Some("str"));
+        assert_eq!(ty.field_type(1), Some("int"));
+        assert_eq!(ty.field_type(2), None);
+    }
+
+    #[test]
+    fn cyclic_typename() {
+        let rt = Runtime::new();
+        let mut capsule = rt.root_capsule();
+        capsule.eval("r := new ()\nr set((r,))").unwrap();
+        let expr: ExprIndex = capsule.parse_sourcecode("r").unwrap();
+        let value = capsule.eval(&expr).unwrap();
+        assert_eq!(value.typename(&mut capsule).to_string(), "ref[(ref,)]");
+    }
+}
+
+#[cfg(test)]
+mod test_expr_str {
+    use super::*;
+    use crate::{data::Nat, runtime::Runtime};
+
+    fn eval(code: &str) -> Fallible<Variant> {
+        let rt = Runtime::new();
+        let mut capsule = rt.root_capsule();
+        let expr: ExprIndex = capsule.parse_sourcecode(code)?;
+        capsule.eval(&expr)
+    }
+
+    #[test]
+    fn str_methods() {
+        let s = |code| match eval(code).unwrap() {
+            Variant::Str(s) => s,
+            _ => panic!("expected a str"),
+        };
+        assert_eq!(s("'  Hello, World  ' trim()"), "Hello, World");
+        assert_eq!(s("'Hello' + ', World'"), "Hello, World");
+        assert_eq!(s("'Hello, World' substring(7, 12)"), "World");
+        assert_eq!(s("'Hello' to_upper()"), "HELLO");
+        assert_eq!(s("'Hello' to_lower()"), "hello");
+        assert_eq!(s("'Hello, World' replace('l', 'L')"), "HeLLo, WorLd");
+        assert_eq!(s("'Hello' chars()[4]"), "o");
+        let b = |code| eval(code).unwrap().to_bool().unwrap();
+        assert!(b("'Hello' starts_with('He')"));
+        assert!(b("'Hello' ends_with('lo')"));
+        assert!(b("'Hello, World' contains('lo, W')"));
+        assert!(!b("'Hello' contains('x')"));
+    }
+
+    #[test]
+    fn str_conversions() {
+        let nat = |code| eval(code).unwrap().to_nat().cloned();
+        assert_eq!(
+            nat("'  Hello, World  ' trim() len()"),
+            Some(Nat::from(12u32))
+        );
+        assert_eq!(nat("'7' to_nat()"), Some(Nat::from(7u32)));
+        let int = |code| eval(code).unwrap().to_int().cloned();
+        assert_eq!(int("'12' to_int() + '-2' to_int()"), Some(10.into()));
+    }
+
+    #[test]
+    fn str_split() {
+        let rt = Runtime::new();
+        let mut capsule = rt.root_capsule();
+        let expr: ExprIndex = capsule.parse_sourcecode("'a,b,,c' split(',')").unwrap();
+        let value = capsule.eval(&expr).unwrap();
+        assert_eq!(value.typename(&mut capsule).to_string(), "vec[str]");
+    }
+
+    #[test]
+    fn str_errors() {
+        let err = |code| eval(code).map(|_| ()).unwrap_err().to_string();
+        assert_eq!(
+            err("'abc' substring(2, 4)"),
+            "value error: substring range 2..4 out of bounds"
+        );
+        assert_eq!(
+            err("'abc' substring(1 negate(), 2)"),
+            "value error: -1 is not a valid size"
+        );
+        assert_eq!(
+            err("'12a' to_int()"),
+            "value error: invalid int literal: \"12a\""
+        );
+        assert_eq!(
+            err("'abc' contains(1)"),
+            "type error: expected 'str', found 'int'"
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_expr_vec {
+    use super::*;
+    use crate::runtime::Runtime;
+
+    fn show(code: &str) -> Fallible<String> {
+        let rt = Runtime::new();
+        let mut capsule = rt.root_capsule();
+        capsule.eval("v := [1, 2, 3, 4]")?;
+        let expr: ExprIndex = capsule.parse_sourcecode(code)?;
+        let value = capsule.eval(&expr)?;
+        value.to_str(&mut capsule)
+    }
+
+    #[test]
+    fn vec_methods() {
+        let show = |code| show(code).unwrap();
+        assert_eq!(show("v push(5)"), "[1, 2, 3, 4, 5]");
+        assert_eq!(show("v pop()"), "([1, 2, 3], 4)");
+        assert_eq!(show("v last()"), "4");
+        assert_eq!(show("v get(1)"), "2");
+        assert_eq!(show("v slice(1, 3)"), "[2, 3]");
+        assert_eq!(show("v + [0]"), "[1, 2, 3, 4, 0]");
+        assert_eq!(show("v map(fn (x) { x * x })"), "[1, 4, 9, 16]");
+        assert_eq!(show("v filter(fn (x) { x > 2 })"), "[3, 4]");
+        assert_eq!(show("v fold(0, fn (acc, x) { acc + x })"), "10");
+        assert_eq!(show("v"), "[1, 2, 3, 4]");
+    }
+
+    #[test]
+    fn vec_errors() {
+        let err = |code| show(code).unwrap_err().to_string();
+        assert_eq!(
+            err("(1, 2)[0]"),
+            "type error: expected 'vec', found '(int, int)'"
+        );
+        assert_eq!(err("[1] map(2)"), "type error: expected 'fn', found 'int'");
+        assert_eq!(err("[] pop()"), "value error: empty vec");
+        assert_eq!(
+            err("v slice(3, 5)"),
+            "value error: range 3..5 out of bounds for length 4"
+        );
+    }
+}
@@ -1,13 +1,16 @@
 mod expr;
 
 use urashima_ast::{
+    expr::impls::Expression,
     program::{Binding, PackageDep, PackageProgram, ScriptProgram},
-    statement::impls::Statement,
+    statement::impls::{Assertion, Statement},
 };
 
 use crate::{
-    capsule::Capsule,
-    error::{Error, Fallible},
+    capsule::{Capsule, Engine},
+    data::{nominal::declare_type, symbol, Variant},
+    error::{AssertionFailure, Error, Fallible},
+    inst,
 };
 
 pub(crate) use self::expr::eval_in_context;
@@ -25,9 +28,13 @@ impl Evaluate for PackageProgram {
         for dep in &self.uses {
             dep.eval(ctx)?;
         }
+        for decl in &self.types {
+            for (name, value) in declare_type(ctx, decl) {
+                ctx.bind(&name, value);
+            }
+        }
         for b in &self.bindings {
-            let value = b.value.eval(ctx)?;
-            ctx.bind(&b.name, value);
+            b.eval(ctx)?;
         }
         Ok(())
     }
@@ -51,6 +58,11 @@ impl Evaluate for Binding {
 
     fn eval(&self, ctx: &mut Capsule<'_>) -> Fallible<Self::Value> {
         let val = self.value.eval(ctx)?;
+        if let (Expression::Fn(_), Variant::Fn(idx)) = (&self.value.node, &val) {
+            if let Some(f) = ctx.environment.get_function_mut(*idx) {
+                f.bind_self(&self.name, val.clone());
+            }
+        }
         ctx.bind(&self.name, val);
         Ok(())
     }
@@ -61,7 +73,14 @@ impl Evaluate for ScriptProgram {
 
     fn eval(&self, ctx: &mut Capsule<'_>) -> Fallible<Self::Value> {
         for stmt in &self.statements {
-            stmt.eval(ctx)?;
+            match ctx.engine {
+                Engine::TreeWalking => stmt.eval(ctx)?,
+                Engine::Bytecode => {
+                    let code = inst::translate_statement(&ctx.expr_arena, stmt)?;
+                    inst::execute(ctx, &code)?;
+                }
+            }
+            ctx.safe_point();
         }
         Ok(())
     }
@@ -79,8 +98,49 @@ impl Evaluate for Statement {
             }
             Statement::Break => Err(Error::loop_break()),
             Statement::Continue => Err(Error::loop_continue()),
+            Statement::Return(_, expr) => {
+                let value = expr.eval(ctx)?;
+                Err(Error::fn_return(value))
+            }
+            Statement::Assert(a) => a.eval(ctx),
+            Statement::Type(decl) => {
+                for (name, value) in declare_type(ctx, decl) {
+                    ctx.bind(&name, value);
+                }
+                Ok(())
+            }
             Statement::Use(dep) => dep.eval(ctx),
-            _ => Err(Error::unimplemented()),
+        }
+    }
+}
+
+impl Evaluate for Assertion {
+    type Value = ();
+
+    fn eval(&self, ctx: &mut Capsule<'_>) -> Fallible<Self::Value> {
+        let (cond, operands) = match self.comparison() {
+            Some((op, left, right)) => {
+                let left = left.eval(ctx)?;
+                let right = right.eval(ctx)?;
+                let cond = left.invoke(ctx, op.node.clone(), std::slice::from_ref(&right))?;
+                (cond, Some((left, right)))
+            }
+            None => (self.cond.eval(ctx)?, None),
+        };
+        match cond {
+            Variant::Bool(true) => Ok(()),
+            Variant::Bool(false) => {
+                let message = match &self.message {
+                    Some(message) => Some(message.eval(ctx)?.to_str(ctx)?),
+                    None => None,
+                };
+                Err(Error::assertion(AssertionFailure {
+                    expr: self.source.clone(),
+                    operands: operands.map(|(left, right)| (left.repr(ctx), right.repr(ctx))),
+                    message,
+                }))
+            }
+            cond => Err(Error::invalid_type(symbol!("bool"), cond.typename(ctx))),
         }
     }
 }
@@ -106,8 +166,19 @@ mod test_stmt {
     use super::*;
     use crate::runtime::Runtime;
 
+    fn run(s: &str) -> Fallible<String> {
+        let rt = Runtime::new();
+        let mut out = Vec::new();
+        {
+            let w = Box::new(io::Cursor::new(&mut out));
+            let mut capsule = Capsule::new(rt.context(), w);
+            let prog: ScriptProgram = capsule.parse_sourcecode(s)?;
+            capsule.eval(&prog)?;
+        }
+        Ok(String::from_utf8(out).unwrap())
+    }
+
     #[test]
-    #[ignore]
     fn closure() {
         let s = r#"
 x := 42
@@ -145,4 +216,208 @@ f() -- 하지만 이 호출은 여전히 42를 출력한다.
         assert_eq!(env.values[0].to_int(), Some(&42.into()));
         assert_eq!(&env.names[0], "foo");
     }
+
+    #[test]
+    fn return_early() {
+        let s = r#"
+f := fn (n) {
+    loop {
+        if true {
+            { return n }
+        }
+        "unreachable" println()
+    }
+    "unreachable" println()
+}
+f(42) println()
+        "#;
+        assert_eq!(run(s).unwrap(), "42\n");
+    }
+
+    #[test]
+    fn return_unit() {
+        let s = r#"
+f := fn {
+    "before" println()
+    return
+    "after" println()
+}
+f()
+        "#;
+        assert_eq!(run(s).unwrap(), "before\n");
+    }
+
+    #[test]
+    fn return_at_top_level() {
+        let err = run("return 42\n").unwrap_err();
+        assert_eq!(err.to_string(), "unexpected return statement");
+    }
+
+    #[test]
+    fn closure_outlives_block() {
+        let s = r#"
+make_adder := fn (n) {
+    fn (x) { x + n }
+}
+add := {
+    n := 40
+    make_adder(2)
+}
+n := 0
+add(n + 40) println()
+        "#;
+        assert_eq!(run(s).unwrap(), "42\n");
+    }
+
+    #[test]
+    fn closure_does_not_see_caller_bindings() {
+        let s = r#"
+f := fn { y println() }
+g := fn {
+    y := 42
+    f()
+}
+g()
+        "#;
+        assert_eq!(run(s).unwrap_err().to_string(), "name error: y");
+    }
+
+    #[test]
+    fn print_rat() {
+        let s = r#"
+(1 / 3 to_rat()) println()
+(0.1 + 0.2) println()
+(1.5 * 2) println()
+"#;
+        assert_eq!(run(s).unwrap(), "1/3\n0.3\n3.0\n");
+    }
+
+    #[test]
+    fn recursive_binding() {
+        let s = r#"
+f := fn (again) {
+    if again {
+        f(false)
+    } else {
+        "done" println()
+    }
+}
+f(true)
+        "#;
+        assert_eq!(run(s).unwrap(), "done\n");
+    }
+
+    #[test]
+    fn mutual_recursion() {
+        let s = r#"
+ping := fn (more) { if more { pong() } else { 'ping' println() } }
+pong := fn { ping(false) }
+ping(true)
+main := fn { helper() }
+helper := fn { 'done' println() }
+main()
+        "#;
+        assert_eq!(run(s).unwrap(), "ping\ndone\n");
+    }
+
+    #[test]
+    fn closure_in_block() {
+        let s = r#"
+x := 1
+f := (if true { y := 2; fn { x + y } })
+x := 10
+f() println()
+        "#;
+        assert_eq!(run(s).unwrap(), "3\n");
+    }
+
+    #[test]
+    fn for_values() {
+        let s = r#"
+for c in 'ab' { c println() }
+for x in [1, 2] { (x * 10) println() }
+for v in (x: 'p', y: 'q') { v println() }
+for x in [] { x println() }
+        "#;
+        assert_eq!(run(s).unwrap(), "a\nb\n10\n20\np\nq\n");
+    }
+
+    #[test]
+    fn range_methods() {
+        let s = r#"
+(0 until(3)) to_str() println()
+(0 until(3)) len() println()
+(1 to(3)) len() println()
+v := [1, 2, 3, 4, 5]
+((v len()) to(6)) len() println()
+        "#;
+        assert_eq!(run(s).unwrap(), "0 until(3)\n3\n3\n2\n");
+    }
+
+    #[test]
+    fn switch_literals() {
+        let s = r#"
+describe := fn (x) {
+    switch x {
+        0 => 'zero'
+        true => 'yes'
+        'a' => 'letter'
+        1.5 => 'one and a half'
+        n if n > 100 => 'big'
+        _ => 'other'
+    }
+}
+describe(0) println()
+describe(true) println()
+describe('a') println()
+describe(1.50) println()
+describe(101) println()
+describe(7) println()
+        "#;
+        assert_eq!(
+            run(s).unwrap(),
+            "zero\nyes\nletter\none and a half\nbig\nother\n"
+        );
+    }
+
+    #[test]
+    fn assert_message() {
+        let err = run("name := 'naru'\nassert name != 'naru', f'got {name}'").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "assertion failed: name != 'naru' (left: 'naru', right: 'naru'): got naru"
+        );
+        let err = run("xs := [1, 2]\nassert xs is_empty()").unwrap_err();
+        assert_eq!(err.to_string(), "assertion failed: xs is_empty()");
+    }
+
+    #[test]
+    fn nominal_record() {
+        let s = r#"
+type Point := (x: int, y: int)
+p := Point(1, 2)
+f'{p}' println()
+p typename() println()
+(p.x + p.y) println()
+(p == Point(1, 2)) println()
+(p == (x: 1, y: 2)) println()
+type Vec2 := (x: int, y: int)
+(p == Vec2(1, 2)) println()
+        "#;
+        assert_eq!(
+            run(s).unwrap(),
+            "Point(x: 1, y: 2)\nPoint\n3\ntrue\nfalse\nfalse\n"
+        );
+    }
+
+    #[test]
+    fn nominal_errors() {
+        let err = run("type Point := (x: int, y: int)\nPoint(1, 'a')").unwrap_err();
+        assert_eq!(err.to_string(), "type error: expected 'int', found 'str'");
+        let err = run("type Point := (x: int, y: int)\nPoint(1)").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "value error: Point takes 2 fields, but 1 were given"
+        );
+    }
 }
@@ -1,6 +1,15 @@
 mod translate;
+mod vm;
 
-use crate::data::{Int, Nat, Symbol};
+use urashima_ast::{expr::Pattern, statement::types::TypeDeclaration};
+use urashima_util::PackagePath;
+
+use crate::data::{Int, Nat, Rat, Symbol};
+
+pub(crate) use self::{
+    translate::translate_statement,
+    vm::{call, execute, Code, FunctionCode},
+};
 
 /// Instruction code
 
@@ -18,22 +27,58 @@ pub enum Instruction {
     End,
     Break(Option<u32>),
     BreakIf(Option<u32>),
+    Continue(Option<u32>),
     Return,
     Call(u32),
     Invoke(u8, u32),
+    /// Start iterating over the value on top of the stack
+    Iter,
+    /// Push the next item of the innermost iteration, or break the loop if there is none
+    IterNext,
+    /// Finish the innermost iteration
+    IterEnd,
+    /// Match the value against the pattern, and push whether it matched. The values of
+    /// `Pattern::references` are on top of the stack, and the value to match is below them.
+    /// On a match, the values bound by the pattern are stored in the locals.
+    Match(Box<Pattern>, Vec<LocalIndex>),
+    /// Raise an error for the value on top of the stack, which no `switch` arm matched
+    NoMatch,
+    /// Raise an assertion failure with the source text of the condition. The operands of
+    /// a comparison are read from the locals, and the message is on top of the stack if the
+    /// flag is set.
+    AssertFail(String, Option<(LocalIndex, LocalIndex)>, bool),
 
     Discard,
     // Select,
     LocalGet(LocalIndex),
     LocalSet(LocalIndex),
     LocalTee(LocalIndex),
+    GlobalGet(Symbol),
+    /// Push the value bound to the name like `GlobalGet`, or unit if it is not bound
+    GlobalTryGet(Symbol),
+    GlobalSet(Symbol),
 
     BoolConst(bool),
     I32Const(i32),
     N32Const(u32),
     IntConst(Int),
     NatConst(Nat),
+    RatConst(Rat),
     StrConst(String),
+    /// Convert the values on top of the stack into strings and join them
+    Format(u32),
 
     MethodRef(Symbol),
+    /// Create a function from the code unit at the index
+    Closure(u32),
+    Record(Vec<Symbol>),
+    Vec(u32),
+    /// Read the field of the record on top of the stack
+    Field(Symbol),
+    /// Read the item of the vec below the index on top of the stack
+    Index,
+    New,
+    Use(PackagePath, Vec<Symbol>),
+    /// Create the declared type, and push the values of its bindings
+    Type(Box<TypeDeclaration>),
 }
@@ -1,73 +1,338 @@
+use std::ops::Range;
+use std::sync::Arc;
+
 use urashima_ast::{
     expr::{
-        impls::Expression, CallExpression, ExprArena, ExprIndex, IfExpression, InvokeExpression,
-        LoopExpression,
+        block::BlockExpression,
+        impls::{Expression, FormatPart},
+        ExprArena, ExprIndex, ForExpression, FunctionExpression, IfExpression, LoopExpression,
+        SwitchExpression,
     },
-    statement::impls::Statement,
+    program::{Binding, PackageDep},
+    span::{Span, Spanned},
+    statement::impls::{Assertion, Statement},
 };
 
-use super::Instruction;
-use crate::data::Int;
+use super::{Code, FunctionCode, Instruction, LocalIndex};
+use crate::{
+    data::Symbol,
+    error::{Error, Fallible, Frame},
+};
 
 struct Ctx<'a> {
-    inst: Vec<Instruction>,
     arena: &'a ExprArena,
+    /// Code units being translated; the last one is the innermost function.
+    units: Vec<Unit>,
+}
+
+#[derive(Default)]
+struct Unit {
+    inst: Vec<Instruction>,
+    functions: Vec<Arc<FunctionCode>>,
+    /// Local bindings of each nested block, innermost last
+    scopes: Vec<Vec<(Symbol, LocalIndex)>>,
+    locals: u32,
+    captures: Vec<(Symbol, LocalIndex)>,
+    /// Call sites, by the position of the instruction which makes the call
+    frames: Vec<(usize, Frame)>,
+    /// Locations of expressions with the range of their instructions, inner ones first
+    spans: Vec<(Range<usize>, Span)>,
+    /// Name of the binding which the function is bound to
+    name: Option<Symbol>,
+}
+
+impl Unit {
+    fn lookup(&self, name: &str) -> Option<LocalIndex> {
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev())
+            .find(|(n, _)| n == name)
+            .map(|(_, idx)| *idx)
+    }
+}
+
+impl<'a> Ctx<'a> {
+    fn new(arena: &'a ExprArena) -> Self {
+        Ctx {
+            arena,
+            units: vec![Unit::default()],
+        }
+    }
+
+    fn unit(&mut self) -> &mut Unit {
+        self.units.last_mut().expect("unreachable")
+    }
+
+    fn emit(&mut self, inst: Instruction) {
+        self.unit().inst.push(inst);
+    }
+
+    /// Emit the instruction which calls a function, recording the call site.
+    fn emit_call(&mut self, inst: Instruction, frame: Frame) {
+        let unit = self.unit();
+        unit.frames.push((unit.inst.len(), frame));
+        unit.inst.push(inst);
+    }
+
+    fn push_scope(&mut self) {
+        self.unit().scopes.push(vec![]);
+    }
+
+    fn pop_scope(&mut self) {
+        self.unit().scopes.pop();
+    }
+
+    /// Bind the value on top of the stack to the name. Bindings at the top level of the
+    /// program go to the capsule environment, and the others get a local slot.
+    fn bind(&mut self, name: &Symbol) {
+        if self.unit().scopes.is_empty() {
+            self.emit(Instruction::GlobalSet(name.clone()));
+            return;
+        }
+        let idx = self.declare(name);
+        self.emit(Instruction::LocalSet(idx));
+    }
+
+    /// Allocate a local slot for the name in the innermost scope.
+    fn declare(&mut self, name: &Symbol) -> LocalIndex {
+        let idx = self.temp();
+        self.unit()
+            .scopes
+            .last_mut()
+            .expect("unreachable")
+            .push((name.clone(), idx));
+        idx
+    }
+
+    /// Allocate a local slot which no name refers to.
+    fn temp(&mut self) -> LocalIndex {
+        let unit = self.unit();
+        unit.locals += 1;
+        unit.locals - 1
+    }
+
+    fn get(&mut self, name: &Symbol) {
+        let depth = self.units.len() - 1;
+        let inst = match self.resolve(depth, name) {
+            Some(idx) => Instruction::LocalGet(idx),
+            None => Instruction::GlobalGet(name.clone()),
+        };
+        self.emit(inst);
+    }
+
+    /// Like `get`, but an unbound name is not an error.
+    fn try_get(&mut self, name: &Symbol) {
+        let depth = self.units.len() - 1;
+        let inst = match self.resolve(depth, name) {
+            Some(idx) => Instruction::LocalGet(idx),
+            None => Instruction::GlobalTryGet(name.clone()),
+        };
+        self.emit(inst);
+    }
+
+    /// Find the local slot of the name in the unit at the given depth. Locals of enclosing
+    /// functions are captured into the function environment, and looked up by their names.
+    fn resolve(&mut self, depth: usize, name: &Symbol) -> Option<LocalIndex> {
+        let unit = &self.units[depth];
+        if let Some(idx) = unit.lookup(name) {
+            return Some(idx);
+        }
+        if depth == 0 || unit.name.as_ref() == Some(name) {
+            return None;
+        }
+        if unit.captures.iter().any(|(n, _)| n == name) {
+            return None;
+        }
+        let idx = self.resolve(depth - 1, name)?;
+        self.units[depth].captures.push((name.clone(), idx));
+        None
+    }
+
+    fn function(&mut self, expr: &FunctionExpression, name: Option<&Symbol>) -> Fallible<()> {
+        let parameters: Vec<Symbol> = expr.parameters.iter().map(|p| p.name()).collect();
+        self.units.push(Unit {
+            scopes: vec![parameters.iter().cloned().zip(0..).collect()],
+            locals: parameters.len() as u32,
+            name: name.cloned(),
+            ..Default::default()
+        });
+        let res = expr.body.translate(self);
+        let unit = self.units.pop().expect("unreachable");
+        res?;
+        let f = FunctionCode {
+            name: unit.name,
+            parameters,
+            captures: unit.captures,
+            code: Code::new(
+                unit.inst,
+                unit.functions,
+                unit.frames,
+                unit.spans,
+                unit.locals,
+            )?,
+        };
+        let functions = &mut self.unit().functions;
+        functions.push(Arc::new(f));
+        let idx = functions.len() as u32 - 1;
+        self.emit(Instruction::Closure(idx));
+        Ok(())
+    }
+
+    fn block(&mut self, blk: &BlockExpression) -> Fallible<()> {
+        self.push_scope();
+        let res = blk.translate(self);
+        self.pop_scope();
+        res
+    }
 }
 
 trait Translate {
-    fn translate(&self, ctx: &mut Ctx<'_>);
+    fn translate(&self, ctx: &mut Ctx<'_>) -> Fallible<()>;
+}
+
+/// Lower a top-level statement into instructions. The span of the statement covers all of
+/// them, so that errors raised by the statement itself are located.
+pub(crate) fn translate_statement(arena: &ExprArena, stmt: &Spanned<Statement>) -> Fallible<Code> {
+    let mut ctx = Ctx::new(arena);
+    stmt.translate(&mut ctx)?;
+    let unit = ctx.units.pop().expect("unreachable");
+    Code::new(
+        unit.inst,
+        unit.functions,
+        unit.frames,
+        unit.spans,
+        unit.locals,
+    )
+}
+
+impl<T> Translate for Spanned<T>
+where
+    T: Translate,
+{
+    fn translate(&self, ctx: &mut Ctx<'_>) -> Fallible<()> {
+        let start = ctx.unit().inst.len();
+        self.node.translate(ctx)?;
+        let unit = ctx.unit();
+        unit.spans.push((start..unit.inst.len(), self.span));
+        Ok(())
+    }
 }
 
 impl Translate for ExprIndex {
-    fn translate(&self, ctx: &mut Ctx<'_>) {
+    fn translate(&self, ctx: &mut Ctx<'_>) -> Fallible<()> {
         ctx.arena[*self].translate(ctx)
     }
 }
 
+/// Only the value of the last expression statement is left on the stack; the enclosing `End`
+/// replaces an empty result with the unit.
+impl Translate for BlockExpression {
+    fn translate(&self, ctx: &mut Ctx<'_>) -> Fallible<()> {
+        for s in self.statements() {
+            s.translate(ctx)?;
+            if let Statement::Expr(_) = &s.node {
+                ctx.emit(Instruction::Discard);
+            }
+        }
+        if let Some(e) = self.returns() {
+            e.translate(ctx)?;
+        }
+        Ok(())
+    }
+}
+
 impl Translate for Expression {
-    fn translate(&self, ctx: &mut Ctx<'_>) {
+    fn translate(&self, ctx: &mut Ctx<'_>) -> Fallible<()> {
         use Expression::*;
         match self {
             False => {
-                ctx.inst.push(Instruction::BoolConst(false));
+                ctx.emit(Instruction::BoolConst(false));
             }
             True => {
-                ctx.inst.push(Instruction::BoolConst(true));
+                ctx.emit(Instruction::BoolConst(true));
             }
             Integral(val) => {
-                ctx.inst.push(Instruction::IntConst(Int::from(*val)));
+                ctx.emit(Instruction::IntConst(val.clone()));
+            }
+            Rational(val) => {
+                ctx.emit(Instruction::RatConst(val.clone()));
             }
             Str(val) => {
-                ctx.inst.push(Instruction::StrConst(val.clone()));
+                ctx.emit(Instruction::StrConst(val.clone()));
+            }
+            Format(parts) => {
+                for part in parts {
+                    match part {
+                        FormatPart::Str(s) => ctx.emit(Instruction::StrConst(s.clone())),
+                        FormatPart::Expr(expr) => expr.translate(ctx)?,
+                    }
+                }
+                ctx.emit(Instruction::Format(parts.len() as u32));
+            }
+            Name(name) => {
+                ctx.get(name);
+            }
+            Record(fields) => {
+                let mut labels = vec![];
+                for (label, value) in fields {
+                    if labels.contains(label) {
+                        return Err(Error::value("All labels in the record should be unique"));
+                    }
+                    value.translate(ctx)?;
+                    labels.push(label.clone());
+                }
+                ctx.emit(Instruction::Record(labels));
+            }
+            Vec(items) => {
+                for item in items {
+                    item.translate(ctx)?;
+                }
+                ctx.emit(Instruction::Vec(items.len() as u32));
+            }
+            Block(blk) => {
+                ctx.emit(Instruction::Block);
+                ctx.block(blk)?;
+                ctx.emit(Instruction::End);
+            }
+            Fn(expr) => {
+                ctx.function(expr, None)?;
+            }
+            New(expr) => {
+                expr.translate(ctx)?;
+                ctx.emit(Instruction::New);
+            }
+            Field(expr, label) => {
+                expr.translate(ctx)?;
+                ctx.emit(Instruction::Field(label.node.clone()));
+            }
+            Index(expr, index) => {
+                expr.translate(ctx)?;
+                index.translate(ctx)?;
+                ctx.emit(Instruction::Index);
             }
             Infix(op, left, right) => {
-                left.translate(ctx);
-                right.translate(ctx);
-                ctx.inst.push(Instruction::MethodRef(op.node.clone()));
-                ctx.inst.push(Instruction::Invoke(2, 0));
+                left.translate(ctx)?;
+                right.translate(ctx)?;
+                ctx.emit(Instruction::MethodRef(op.node.clone()));
+                ctx.emit(Instruction::Invoke(2, 0));
             }
-            Call(CallExpression {
-                callee, arguments, ..
-            }) => {
-                callee.translate(ctx);
-                for a in &arguments.node {
-                    a.translate(ctx);
+            Call(expr) => {
+                expr.callee.translate(ctx)?;
+                for a in &expr.arguments.node {
+                    a.translate(ctx)?;
                 }
-                ctx.inst.push(Instruction::Call(arguments.len() as u32));
+                let frame = Frame::call(ctx.arena, expr);
+                ctx.emit_call(Instruction::Call(expr.arguments.len() as u32), frame);
             }
-            Invoke(InvokeExpression {
-                receiver,
-                method,
-                arguments,
-                ..
-            }) => {
-                receiver.translate(ctx);
-                for a in &arguments.node {
-                    a.translate(ctx);
+            Invoke(expr) => {
+                expr.receiver.translate(ctx)?;
+                for a in &expr.arguments.node {
+                    a.translate(ctx)?;
                 }
-                ctx.inst.push(Instruction::MethodRef(method.node.clone()));
-                ctx.inst
-                    .push(Instruction::Invoke(1, arguments.len() as u32));
+                ctx.emit(Instruction::MethodRef(expr.method.node.clone()));
+                let inst = Instruction::Invoke(1, expr.arguments.len() as u32);
+                ctx.emit_call(inst, Frame::invoke(expr));
             }
             If(IfExpression {
                 cond,
@@ -75,43 +340,182 @@ impl Translate for Expression {
                 else_blk,
                 ..
             }) => {
-                cond.translate(ctx);
-                ctx.inst.push(Instruction::If);
-                for s in &then_blk.node {
-                    s.translate(ctx);
-                }
+                cond.translate(ctx)?;
+                ctx.emit(Instruction::If);
+                ctx.block(then_blk)?;
                 if let Some(else_blk) = else_blk {
-                    ctx.inst.push(Instruction::Else);
-                    for s in &else_blk.node {
-                        s.translate(ctx);
-                    }
+                    ctx.emit(Instruction::Else);
+                    ctx.block(else_blk)?;
                 }
-                ctx.inst.push(Instruction::End);
+                ctx.emit(Instruction::End);
             }
             Loop(LoopExpression { blk, .. }) => {
-                ctx.inst.push(Instruction::Loop(None));
-                for s in &blk.node {
-                    s.translate(ctx);
-                }
-                ctx.inst.push(Instruction::End);
+                ctx.emit(Instruction::Loop(None));
+                ctx.block(blk)?;
+                ctx.emit(Instruction::End);
+            }
+            For(ForExpression {
+                binding,
+                iterable,
+                blk,
+                ..
+            }) => {
+                iterable.translate(ctx)?;
+                ctx.emit(Instruction::Iter);
+                ctx.emit(Instruction::Loop(None));
+                ctx.push_scope();
+                ctx.emit(Instruction::IterNext);
+                ctx.bind(&binding.node);
+                let res = ctx.block(blk);
+                ctx.pop_scope();
+                res?;
+                ctx.emit(Instruction::End);
+                ctx.emit(Instruction::IterEnd);
+            }
+            Switch(expr) => {
+                expr.translate(ctx)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The subject and the result are kept in temporary locals, since `Break` leaves only the unit
+/// on the stack. Each arm is an `If` on the result of `Match`, which breaks out of the enclosing
+/// `Block` after the body; falling through all arms reaches `NoMatch`.
+impl Translate for SwitchExpression {
+    fn translate(&self, ctx: &mut Ctx<'_>) -> Fallible<()> {
+        let subject = ctx.temp();
+        let result = ctx.temp();
+        self.subject.translate(ctx)?;
+        ctx.emit(Instruction::LocalSet(subject));
+        ctx.emit(Instruction::Block);
+        for arm in &self.arms {
+            ctx.emit(Instruction::LocalGet(subject));
+            for name in arm.pattern.node.bindings() {
+                ctx.try_get(name);
+            }
+            for name in arm.pattern.node.tags() {
+                ctx.get(name);
+            }
+            ctx.push_scope();
+            let locals = arm
+                .pattern
+                .node
+                .bindings()
+                .into_iter()
+                .map(|name| ctx.declare(name))
+                .collect();
+            ctx.emit(Instruction::Match(
+                Box::new(arm.pattern.node.clone()),
+                locals,
+            ));
+            ctx.emit(Instruction::If);
+            let res = arm.guard.map_or(Ok(()), |guard| {
+                guard.translate(ctx)?;
+                ctx.emit(Instruction::If);
+                Ok(())
+            });
+            let res = res.and_then(|()| arm.body.translate(ctx));
+            ctx.pop_scope();
+            res?;
+            let depth = if arm.guard.is_some() { 2 } else { 1 };
+            ctx.emit(Instruction::LocalSet(result));
+            ctx.emit(Instruction::Break(Some(depth)));
+            if arm.guard.is_some() {
+                ctx.emit(Instruction::End);
+            }
+            ctx.emit(Instruction::End);
+            ctx.emit(Instruction::Discard);
+        }
+        ctx.emit(Instruction::LocalGet(subject));
+        ctx.emit(Instruction::NoMatch);
+        ctx.emit(Instruction::End);
+        ctx.emit(Instruction::Discard);
+        ctx.emit(Instruction::LocalGet(result));
+        Ok(())
+    }
+}
+
+/// The condition is checked within a `Block`, which is left as soon as it holds; the operands
+/// of a comparison are kept in temporary locals to be reported on a failure.
+impl Translate for Assertion {
+    fn translate(&self, ctx: &mut Ctx<'_>) -> Fallible<()> {
+        ctx.emit(Instruction::Block);
+        let operands = match self.comparison() {
+            Some((op, left, right)) => {
+                let (l, r) = (ctx.temp(), ctx.temp());
+                left.translate(ctx)?;
+                ctx.emit(Instruction::LocalTee(l));
+                right.translate(ctx)?;
+                ctx.emit(Instruction::LocalTee(r));
+                ctx.emit(Instruction::MethodRef(op.node.clone()));
+                ctx.emit(Instruction::Invoke(2, 0));
+                Some((l, r))
             }
-            _ => unimplemented!(),
+            None => {
+                self.cond.translate(ctx)?;
+                None
+            }
+        };
+        ctx.emit(Instruction::BreakIf(Some(0)));
+        if let Some(message) = &self.message {
+            message.translate(ctx)?;
         }
+        let inst = Instruction::AssertFail(self.source.clone(), operands, self.message.is_some());
+        ctx.emit(inst);
+        ctx.emit(Instruction::End);
+        ctx.emit(Instruction::Discard);
+        Ok(())
     }
 }
 
 impl Translate for Statement {
-    fn translate(&self, ctx: &mut Ctx<'_>) {
+    fn translate(&self, ctx: &mut Ctx<'_>) -> Fallible<()> {
         use Statement::*;
         match self {
+            Binding(b) => {
+                b.translate(ctx)?;
+            }
             Expr(expr) => {
-                expr.translate(ctx);
+                expr.translate(ctx)?;
+            }
+            Return(_, expr) => {
+                expr.translate(ctx)?;
+                ctx.emit(Instruction::Return);
             }
             Break => {
-                ctx.inst.push(Instruction::Break(None));
+                ctx.emit(Instruction::Break(None));
+            }
+            Continue => {
+                ctx.emit(Instruction::Continue(None));
+            }
+            Assert(a) => {
+                a.translate(ctx)?;
+            }
+            Type(decl) => {
+                ctx.emit(Instruction::Type(Box::new(decl.clone())));
+                for name in decl.bindings().into_iter().rev() {
+                    ctx.bind(name);
+                }
+            }
+            Use(PackageDep { path, imports }) => {
+                ctx.emit(Instruction::Use(path.clone(), imports.clone()));
             }
-            _ => unimplemented!(),
         }
+        Ok(())
+    }
+}
+
+impl Translate for Binding {
+    fn translate(&self, ctx: &mut Ctx<'_>) -> Fallible<()> {
+        if let Expression::Fn(expr) = &self.value.node {
+            ctx.function(expr, Some(&self.name))?;
+        } else {
+            self.value.translate(ctx)?;
+        }
+        ctx.bind(&self.name);
+        Ok(())
     }
 }
 
@@ -120,7 +524,7 @@ mod test {
     use urashima_ast::{expr::ExprArena, parse};
 
     use super::*;
-    use crate::data::Symbol;
+    use crate::data::{Int, Symbol};
 
     macro_rules! assert_translate {
         ($name:ident: $t:ty = $code:literal; $($inst:expr,)*) => {
@@ -130,13 +534,10 @@ mod test {
                 let s = $code;
                 let mut arena = ExprArena::new();
                 let expr: $t = parse(&mut arena, s).unwrap();
-                let mut ctx = Ctx {
-                    inst: vec![],
-                    arena: &arena,
-                };
-                expr.translate(&mut ctx);
+                let mut ctx = Ctx::new(&arena);
+                expr.translate(&mut ctx).unwrap();
                 assert_eq!(
-                    &ctx.inst,
+                    &ctx.unit().inst,
                     &[$(
                         $inst,
                     )*],
@@ -181,4 +582,43 @@ mod test {
         IntConst(Int::from(43)),
         End,
     }
+
+    assert_translate! {
+        binding_local: Expression = "{ x := 1; x + 2 }";
+        Block,
+        IntConst(Int::from(1)),
+        LocalSet(0),
+        LocalGet(0),
+        IntConst(Int::from(2)),
+        MethodRef(Symbol::from("+")),
+        Invoke(2, 0),
+        End,
+    }
+
+    assert_translate! {
+        binding_global: Statement = "x := y";
+        GlobalGet(Symbol::from("y")),
+        GlobalSet(Symbol::from("x")),
+    }
+
+    #[test]
+    fn closure_captures() {
+        let mut arena = ExprArena::new();
+        let expr: Expression = parse(&mut arena, "{ a := 1; b := 2; fn (c) { b + c } }").unwrap();
+        let mut ctx = Ctx::new(&arena);
+        expr.translate(&mut ctx).unwrap();
+        let unit = ctx.unit();
+        assert_eq!(unit.functions.len(), 1);
+        let f = &unit.functions[0];
+        assert_eq!(f.captures, vec![(Symbol::from("b"), 1)]);
+        assert_eq!(
+            f.code.instructions(),
+            &[
+                Instruction::GlobalGet(Symbol::from("b")),
+                Instruction::LocalGet(0),
+                Instruction::MethodRef(Symbol::from("+")),
+                Instruction::Invoke(2, 0),
+            ]
+        );
+    }
 }
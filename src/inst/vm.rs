@@ -0,0 +1,1039 @@
+//! Stack-based virtual machine which runs translated instructions
+
+use std::ops::Range;
+use std::sync::Arc;
+
+use urashima_ast::{program::PackageDep, span::Span};
+
+use super::{Instruction, LocalIndex};
+use crate::{
+    capsule::Capsule,
+    data::{
+        iter, nominal::declare_type, pattern::match_pattern, symbol, Function, Int, Nat, Symbol,
+        Variant,
+    },
+    error::{AssertionFailure, Error, Fallible, Frame},
+    eval::Evaluate,
+};
+
+/// Translated instructions, with the structured control flow resolved into jump targets
+pub(crate) struct Code {
+    inst: Vec<Instruction>,
+    /// For `Block`, `Loop`, `If` and `Else`, the position of the matching `Else` or `End`
+    jumps: Vec<usize>,
+    /// Functions created by `Closure` instructions
+    functions: Vec<Arc<FunctionCode>>,
+    /// Call sites, by the position of the `Call` or `Invoke` instruction
+    frames: Vec<(usize, Frame)>,
+    /// Locations of expressions with the range of their instructions, inner ones first
+    spans: Vec<(Range<usize>, Span)>,
+    locals: u32,
+}
+
+/// Code unit of a function body
+pub(crate) struct FunctionCode {
+    /// Name of the binding which the function is bound to, so that it can refer itself
+    pub(crate) name: Option<Symbol>,
+    pub(crate) parameters: Vec<Symbol>,
+    /// Locals of the enclosing code which are captured when the function is created
+    pub(crate) captures: Vec<(Symbol, LocalIndex)>,
+    pub(crate) code: Code,
+}
+
+impl Code {
+    pub(crate) fn new(
+        inst: Vec<Instruction>,
+        functions: Vec<Arc<FunctionCode>>,
+        frames: Vec<(usize, Frame)>,
+        spans: Vec<(Range<usize>, Span)>,
+        locals: u32,
+    ) -> Fallible<Self> {
+        let mut jumps = vec![0; inst.len()];
+        let mut opened = vec![];
+        for (pc, i) in inst.iter().enumerate() {
+            match i {
+                Instruction::Block | Instruction::Loop(_) | Instruction::If => opened.push(pc),
+                Instruction::Else => {
+                    let start = opened.pop().ok_or_else(Error::runtime)?;
+                    jumps[start] = pc;
+                    opened.push(pc);
+                }
+                Instruction::End => {
+                    let start = opened.pop().ok_or_else(Error::runtime)?;
+                    jumps[start] = pc;
+                }
+                _ => {}
+            }
+        }
+        if !opened.is_empty() {
+            return Err(Error::runtime());
+        }
+        Ok(Code {
+            inst,
+            jumps,
+            functions,
+            frames,
+            spans,
+            locals,
+        })
+    }
+
+    fn frame(&self, pc: usize) -> Option<&Frame> {
+        let i = self.frames.binary_search_by_key(&pc, |(i, _)| *i).ok()?;
+        Some(&self.frames[i].1)
+    }
+
+    /// Location of the innermost expression which the instruction at the position belongs to
+    fn span(&self, pc: usize) -> Option<Span> {
+        self.spans
+            .iter()
+            .find(|(range, _)| range.contains(&pc))
+            .map(|(_, span)| *span)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn instructions(&self) -> &[Instruction] {
+        &self.inst
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum LabelKind {
+    Block,
+    Loop,
+    If,
+}
+
+#[derive(Clone, Copy)]
+struct Label {
+    kind: LabelKind,
+    /// Position of the first instruction in the block
+    start: usize,
+    /// Position of the `End` instruction which closes the block
+    end: usize,
+    /// Height of the operand stack when the block is entered
+    height: usize,
+}
+
+#[derive(Default)]
+struct Machine {
+    stack: Vec<Variant>,
+    labels: Vec<Label>,
+    locals: Vec<Variant>,
+    method: Option<Symbol>,
+    iters: Vec<iter::Iter>,
+}
+
+enum Exit {
+    End,
+    Return,
+}
+
+/// Run the code to the end, and returns the value left on top of the stack.
+pub(crate) fn execute(ctx: &mut Capsule<'_>, code: &Code) -> Fallible<Variant> {
+    let mut vm = Machine::new(code, vec![]);
+    let exit = vm.run(ctx, code)?;
+    let value = vm.stack.pop().unwrap_or_else(Variant::unit);
+    match exit {
+        Exit::End => Ok(value),
+        Exit::Return => Err(Error::fn_return(value)),
+    }
+}
+
+/// Run the function body with the arguments. The function environment should be entered already.
+pub(crate) fn call(
+    ctx: &mut Capsule<'_>,
+    f: &FunctionCode,
+    args: Vec<Variant>,
+) -> Fallible<Variant> {
+    if let Some(name) = f.parameters.get(args.len()) {
+        return Err(Error::name(name.clone()));
+    }
+    let mut args = args;
+    args.truncate(f.parameters.len());
+    let mut vm = Machine::new(&f.code, args);
+    vm.run(ctx, &f.code)?;
+    Ok(vm.stack.pop().unwrap_or_else(Variant::unit))
+}
+
+impl Machine {
+    fn new(code: &Code, mut locals: Vec<Variant>) -> Self {
+        locals.resize_with(code.locals as usize, Variant::unit);
+        Machine {
+            locals,
+            ..Default::default()
+        }
+    }
+
+    fn run(&mut self, ctx: &mut Capsule<'_>, code: &Code) -> Fallible<Exit> {
+        let mut pc = 0;
+        self.run_from(ctx, code, &mut pc)
+            .map_err(|e| match code.span(pc.saturating_sub(1)) {
+                Some(span) => e.with_span(span, ctx.environment.path()),
+                None => e,
+            })
+    }
+
+    fn run_from(&mut self, ctx: &mut Capsule<'_>, code: &Code, pc: &mut usize) -> Fallible<Exit> {
+        use Instruction::*;
+
+        while let Some(inst) = code.inst.get(*pc) {
+            *pc += 1;
+            match inst {
+                Unreachable => return Err(Error::runtime()),
+                Nop => {}
+                Block => self.enter(LabelKind::Block, *pc, code.jumps[*pc - 1]),
+                Loop(_) => self.enter(LabelKind::Loop, *pc, code.jumps[*pc - 1]),
+                If => {
+                    let target = code.jumps[*pc - 1];
+                    if self.pop_bool(ctx)? {
+                        let end = match code.inst[target] {
+                            Else => code.jumps[target],
+                            _ => target,
+                        };
+                        self.enter(LabelKind::If, *pc, end);
+                    } else if let Else = code.inst[target] {
+                        self.enter(LabelKind::If, target + 1, code.jumps[target]);
+                        *pc = target + 1;
+                    } else {
+                        self.stack.push(Variant::unit());
+                        *pc = target + 1;
+                    }
+                }
+                Else | End => {
+                    let label = self.labels.pop().ok_or_else(Error::runtime)?;
+                    if label.kind == LabelKind::Loop {
+                        self.stack.truncate(label.height);
+                        self.labels.push(label);
+                        *pc = label.start;
+                    } else {
+                        self.leave(label);
+                        *pc = label.end + 1;
+                    }
+                }
+                Break(depth) => *pc = self.break_to(*depth)?,
+                BreakIf(depth) => {
+                    if self.pop_bool(ctx)? {
+                        *pc = self.break_to(*depth)?;
+                    }
+                }
+                Continue(depth) => *pc = self.continue_to(*depth)?,
+                Return => return Ok(Exit::Return),
+                Iter => {
+                    let value = self.pop()?;
+                    self.iters.push(iter::Iter::new(ctx, value)?);
+                }
+                IterNext => {
+                    let iter = self.iters.last_mut().ok_or_else(Error::runtime)?;
+                    match iter.next(ctx)? {
+                        Some(item) => self.stack.push(item),
+                        None => *pc = self.break_to(None)?,
+                    }
+                }
+                IterEnd => {
+                    self.iters.pop().ok_or_else(Error::runtime)?;
+                }
+                Match(pattern, locals) => {
+                    let scope = self.pop_n(pattern.references().len())?;
+                    let value = self.pop()?;
+                    let bound = match_pattern(ctx, pattern, &value, &scope);
+                    self.stack.push(Variant::Bool(bound.is_some()));
+                    for (idx, value) in locals.iter().zip(bound.unwrap_or_default()) {
+                        self.set_local(*idx, value);
+                    }
+                }
+                NoMatch => {
+                    let value = self.pop()?;
+                    return Err(Error::no_match(value.repr(ctx)));
+                }
+                AssertFail(expr, operands, has_message) => {
+                    let message = if *has_message {
+                        Some(self.pop()?.to_str(ctx)?)
+                    } else {
+                        None
+                    };
+                    let operands = match operands {
+                        Some((left, right)) => {
+                            let local = |idx: LocalIndex| self.locals.get(idx as usize);
+                            let left = local(*left).ok_or_else(Error::runtime)?;
+                            let right = local(*right).ok_or_else(Error::runtime)?;
+                            Some((left.repr(ctx), right.repr(ctx)))
+                        }
+                        None => None,
+                    };
+                    return Err(Error::assertion(AssertionFailure {
+                        expr: expr.clone(),
+                        operands,
+                        message,
+                    }));
+                }
+                Call(argc) => {
+                    let args = self.pop_n(*argc as usize)?;
+                    let callee = self.pop()?;
+                    let f = match callee.as_function(ctx) {
+                        Some(f) => f.clone(),
+                        None => {
+                            return Err(Error::invalid_type(symbol!("fn"), callee.typename(ctx)))
+                        }
+                    };
+                    let value = match code.frame(*pc - 1) {
+                        Some(frame) => ctx.call_frame(frame.clone(), |ctx| f.apply(ctx, args))?,
+                        None => f.apply(ctx, args)?,
+                    };
+                    self.stack.push(value);
+                }
+                Invoke(operands, argc) => {
+                    let method = self.method.take().ok_or_else(Error::runtime)?;
+                    let mut args = self.pop_n(*operands as usize + *argc as usize)?;
+                    if args.is_empty() {
+                        return Err(Error::runtime());
+                    }
+                    let receiver = args.remove(0);
+                    let value = match code.frame(*pc - 1) {
+                        Some(frame) if receiver.has_fn_method(ctx, &method) => ctx
+                            .call_frame(frame.clone(), |ctx| receiver.invoke(ctx, method, &args))?,
+                        _ => receiver.invoke(ctx, method, &args)?,
+                    };
+                    self.stack.push(value);
+                }
+
+                Discard => {
+                    self.pop()?;
+                }
+                LocalGet(idx) => {
+                    let value = self
+                        .locals
+                        .get(*idx as usize)
+                        .cloned()
+                        .ok_or_else(Error::runtime)?;
+                    self.stack.push(value);
+                }
+                LocalSet(idx) => {
+                    let value = self.pop()?;
+                    self.set_local(*idx, value);
+                }
+                LocalTee(idx) => {
+                    let value = self.stack.last().cloned().ok_or_else(Error::runtime)?;
+                    self.set_local(*idx, value);
+                }
+                GlobalGet(name) => {
+                    let value = ctx.environment.lookup_name(name)?.clone();
+                    self.stack.push(value);
+                }
+                GlobalTryGet(name) => {
+                    let value = match ctx.environment.lookup_name(name) {
+                        Ok(value) => value.clone(),
+                        Err(_) => Variant::unit(),
+                    };
+                    self.stack.push(value);
+                }
+                GlobalSet(name) => {
+                    let value = self.pop()?;
+                    ctx.bind(name, value);
+                }
+                Closure(idx) => {
+                    let f = code
+                        .functions
+                        .get(*idx as usize)
+                        .ok_or_else(Error::runtime)?;
+                    let value = self.closure(ctx, f)?;
+                    self.stack.push(value);
+                }
+                Record(labels) => {
+                    let values = self.pop_n(labels.len())?;
+                    let record = labels
+                        .iter()
+                        .cloned()
+                        .zip(values)
+                        .map(|(label, value)| (label, ctx.environment.boxed(value)))
+                        .collect();
+                    self.stack.push(Variant::Record(record));
+                }
+                Format(count) => {
+                    let mut buf = String::new();
+                    for value in self.pop_n(*count as usize)? {
+                        buf.push_str(&value.to_str(ctx)?);
+                    }
+                    self.stack.push(Variant::Str(buf));
+                }
+                Vec(count) => {
+                    let items = self.pop_n(*count as usize)?;
+                    self.stack.push(Variant::Vec(items));
+                }
+                Index => {
+                    let index = self.pop()?;
+                    let value = self.pop()?.index(ctx, index)?;
+                    self.stack.push(value);
+                }
+                Field(label) => {
+                    let value = self.pop()?.field(ctx, label)?;
+                    self.stack.push(value);
+                }
+                New => {
+                    let value = self.pop()?;
+                    self.stack.push(Variant::Ref(ctx.environment.boxed(value)));
+                }
+                Use(path, imports) => {
+                    let dep = PackageDep {
+                        path: path.clone(),
+                        imports: imports.clone(),
+                    };
+                    dep.eval(ctx)?;
+                }
+                Type(decl) => {
+                    for (_, value) in declare_type(ctx, decl) {
+                        self.stack.push(value);
+                    }
+                }
+
+                BoolConst(val) => self.stack.push(Variant::Bool(*val)),
+                I32Const(val) => self.stack.push(Variant::Int(Int::from(*val))),
+                N32Const(val) => self.stack.push(Variant::Nat(Nat::from(*val))),
+                IntConst(val) => self.stack.push(Variant::Int(val.clone())),
+                NatConst(val) => self.stack.push(Variant::Nat(val.clone())),
+                RatConst(val) => self.stack.push(Variant::from(val.clone())),
+                StrConst(val) => self.stack.push(Variant::from(&val[..])),
+
+                MethodRef(method) => self.method = Some(method.clone()),
+            }
+        }
+        Ok(Exit::End)
+    }
+
+    fn enter(&mut self, kind: LabelKind, start: usize, end: usize) {
+        self.labels.push(Label {
+            kind,
+            start,
+            end,
+            height: self.stack.len(),
+        });
+    }
+
+    /// Leave the block, keeping only its result on the stack.
+    fn leave(&mut self, label: Label) {
+        let value = if self.stack.len() > label.height {
+            self.stack.pop()
+        } else {
+            None
+        };
+        self.stack.truncate(label.height);
+        self.stack.push(value.unwrap_or_else(Variant::unit));
+    }
+
+    /// Exit the block at the given depth, or the innermost loop if no depth is given.
+    /// Returns the position to continue.
+    fn break_to(&mut self, depth: Option<u32>) -> Fallible<usize> {
+        let pos = match depth {
+            Some(depth) => self
+                .labels
+                .len()
+                .checked_sub(depth as usize + 1)
+                .ok_or_else(Error::runtime)?,
+            None => self
+                .labels
+                .iter()
+                .rposition(|l| l.kind == LabelKind::Loop)
+                .ok_or_else(Error::loop_break)?,
+        };
+        let label = self.labels[pos];
+        self.labels.truncate(pos);
+        self.stack.truncate(label.height);
+        self.stack.push(Variant::unit());
+        Ok(label.end + 1)
+    }
+
+    /// Exit the blocks within the loop at the given depth, or the innermost loop if no depth is
+    /// given, and start the next iteration. Returns the position to continue.
+    fn continue_to(&mut self, depth: Option<u32>) -> Fallible<usize> {
+        let pos = match depth {
+            Some(depth) => self
+                .labels
+                .len()
+                .checked_sub(depth as usize + 1)
+                .filter(|&pos| self.labels[pos].kind == LabelKind::Loop)
+                .ok_or_else(Error::runtime)?,
+            None => self
+                .labels
+                .iter()
+                .rposition(|l| l.kind == LabelKind::Loop)
+                .ok_or_else(Error::loop_continue)?,
+        };
+        let label = self.labels[pos];
+        self.labels.truncate(pos + 1);
+        self.stack.truncate(label.height);
+        Ok(label.start)
+    }
+
+    fn closure(&self, ctx: &mut Capsule<'_>, f: &Arc<FunctionCode>) -> Fallible<Variant> {
+        let mut environment = ctx.environment.capture();
+        for (name, idx) in &f.captures {
+            let value = self
+                .locals
+                .get(*idx as usize)
+                .cloned()
+                .ok_or_else(Error::runtime)?;
+            environment.bind(name, value);
+        }
+        let idx = ctx
+            .environment
+            .add_function(Function::from_code(Arc::clone(f), environment));
+        let value = Variant::Fn(idx);
+        if let (Some(name), Some(f)) = (&f.name, ctx.environment.get_function_mut(idx)) {
+            f.bind_self(name, value.clone());
+        }
+        Ok(value)
+    }
+
+    fn set_local(&mut self, idx: u32, value: Variant) {
+        let idx = idx as usize;
+        if self.locals.len() <= idx {
+            self.locals.resize_with(idx + 1, Variant::unit);
+        }
+        self.locals[idx] = value;
+    }
+
+    fn pop(&mut self) -> Fallible<Variant> {
+        self.stack.pop().ok_or_else(Error::runtime)
+    }
+
+    fn pop_n(&mut self, n: usize) -> Fallible<Vec<Variant>> {
+        let at = self.stack.len().checked_sub(n).ok_or_else(Error::runtime)?;
+        Ok(self.stack.split_off(at))
+    }
+
+    fn pop_bool(&mut self, ctx: &mut Capsule<'_>) -> Fallible<bool> {
+        let value = self.pop()?;
+        value
+            .to_bool()
+            .ok_or_else(|| Error::invalid_type(symbol!("bool"), value.typename(ctx)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io;
+
+    use urashima_ast::{program::ScriptProgram, span::Position};
+
+    use crate::{capsule::Engine, runtime::Runtime};
+
+    fn run(engine: Engine, s: &str) -> Result<String, String> {
+        let rt = Runtime::new();
+        let mut out = Vec::new();
+        {
+            let mut capsule = rt
+                .capsule_builder()
+                .stdout(Box::new(io::Cursor::new(&mut out)))
+                .engine(engine)
+                .build();
+            let prog: ScriptProgram = capsule.parse_sourcecode(s).map_err(|e| e.to_string())?;
+            capsule.eval(&prog).map_err(|e| e.to_string())?;
+        }
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    macro_rules! assert_same {
+        ($name:ident: $code:expr => $expected:expr) => {
+            #[test]
+            fn $name() {
+                let expected: Result<&str, &str> = $expected;
+                let expected = expected.map(String::from).map_err(String::from);
+                assert_eq!(run(Engine::TreeWalking, $code), expected);
+                assert_eq!(run(Engine::Bytecode, $code), expected);
+            }
+        };
+    }
+
+    assert_same! {
+        helloworld: include_str!("../../tests/helloworld.n") => Ok("Hello, world!\n")
+    }
+
+    assert_same! {
+        arithmetic: "(1 + 2 * 3) println()\n(1.5 * 2) println()" => Ok("7\n3.0\n")
+    }
+
+    assert_same! {
+        if_value: r#"
+(if 1 < 2 { 'yes' } else { 'no' }) println()
+(if 1 > 2 { 'yes' } else { 'no' }) println()
+"# => Ok("yes\nno\n")
+    }
+
+    assert_same! {
+        if_without_else: "(if false { 1 }) println()" => Err("name error: () has no method 'println'")
+    }
+
+    assert_same! {
+        block_value: "(if true { 1; 2 } + 3) println()" => Ok("5\n")
+    }
+
+    assert_same! {
+        loop_break: r#"
+loop {
+    'once' println()
+    if true {
+        break
+    }
+    'unreachable' println()
+}
+"# => Ok("once\n")
+    }
+
+    assert_same! {
+        call_function: r#"
+f := fn (a, b) { a + b }
+(f(1, 2) * 2) println()
+"# => Ok("6\n")
+    }
+
+    assert_same! {
+        closure: r#"
+x := 42
+f := fn {
+    x println()
+}
+x := "foo"
+x println()
+f()
+"# => Ok("foo\n42\n")
+    }
+
+    assert_same! {
+        missing_argument: "f := fn (a, b) { a println() }\nf(1)" => Err("name error: b")
+    }
+
+    assert_same! {
+        forward_reference: r#"
+is_even := fn (n) { if n == 0 { true } else { is_odd(n - 1) } }
+is_odd := fn (n) { if n == 0 { false } else { is_even(n - 1) } }
+is_even(7) println()
+"# => Ok("false\n")
+    }
+
+    assert_same! {
+        block_locals: r#"
+{
+    x := 1
+    y := {
+        x := x + 1
+        x * 10
+    }
+    (x + y) println()
+}
+"# => Ok("21\n")
+    }
+
+    assert_same! {
+        capture_locals: r#"
+f := {
+    y := 5
+    fn (a) { a + y }
+}
+f(1) println()
+g := {
+    a := 1
+    fn {
+        b := 2
+        fn { a + b }
+    }
+}
+g()() println()
+"# => Ok("6\n3\n")
+    }
+
+    assert_same! {
+        recursion: r#"
+fact := fn (n) {
+    if n < 2 {
+        return 1
+    }
+    n * fact(n - 1)
+}
+fact(10) println()
+{
+    fib := fn (n) {
+        if n < 2 { n } else { fib(n - 1) + fib(n - 2) }
+    }
+    fib(10) println()
+}
+"# => Ok("3628800\n55\n")
+    }
+
+    assert_same! {
+        return_from_loop: r#"
+f := fn {
+    loop {
+        if true {
+            return 'done'
+        }
+    }
+}
+f() println()
+"# => Ok("done\n")
+    }
+
+    assert_same! {
+        loop_continue: r#"
+loop {
+    'a' println()
+    if false {
+        continue
+    }
+    break
+}
+"# => Ok("a\n")
+    }
+
+    assert_same! {
+        example: "main := fn { if 1 < 2 { 'Hello?' println() }; return }\nmain()" => Ok("Hello?\n")
+    }
+
+    assert_same! {
+        ref_counter: r#"
+count := new 0
+incr := fn { count set(count get() + 1) }
+loop {
+    if count get() >= 3 {
+        break
+    }
+    incr()
+}
+count get() println()
+"# => Ok("3\n")
+    }
+
+    assert_same! {
+        record_literal: r#"
+((x: 1, y: 2) == (x: 1, y: 2)) println()
+((1, 2,) == (1, 3)) println()
+(() == ()) println()
+(greet: fn (self, name) { name println() }) greet('hi')
+"# => Ok("true\nfalse\ntrue\nhi\n")
+    }
+
+    assert_same! {
+        record_field: r#"
+p := (x: 1, y: 'a')
+p.x println()
+p.1 println()
+((1, 2), 3).0.1 println()
+"# => Ok("1\na\n2\n")
+    }
+
+    assert_same! {
+        record_missing_field: "(x: 1, y: 2).z" => Err("name error: (x: int, y: int) has no field 'z'")
+    }
+
+    assert_same! {
+        field_of_non_record: "1.x" => Err("name error: int has no field 'x'")
+    }
+
+    assert_same! {
+        record_duplicate_label: "(x: 1, x: 2)" => Err("value error: All labels in the record should be unique")
+    }
+
+    assert_same! {
+        name_error: "f := fn { y }\nf()" => Err("name error: y")
+    }
+
+    assert_same! {
+        return_at_top_level: "return 1" => Err("unexpected return statement")
+    }
+
+    assert_same! {
+        type_error: "1 + 'a'" => Err("type error: expected 'int', found 'str'")
+    }
+
+    assert_same! {
+        format_string: r#"
+name := 'Naru'
+p := (x: 1, y: 0.5, label: 'a\tb')
+f'Hello, {name}! {1 + 2} {true}' println()
+f'{p} {(1,)} {new 2} {{}}' println()
+v := (value: 42, to_str: fn (self) { f'<{self.value}>' })
+f'{v}!' println()
+"# => Ok("Hello, Naru! 3 true\n(x: 1, y: 0.5, label: 'a\\tb') (1,) new 2 {}\n<42>!\n")
+    }
+
+    assert_same! {
+        format_string_bad_to_str: "v := (to_str: fn (self) { 1 })\nf'{v}'" => Err("type error: expected 'str', found 'int'")
+    }
+
+    assert_same! {
+        vec_literal: r#"
+v := [1, 2, 3,]
+v println()
+v[0] println()
+v len() println()
+v typename() println()
+[] typename() println()
+[1, 'a'] typename() println()
+([1, 2] == [1, 2]) println()
+[[1], ['x']] println()
+'a,b,,c' split(',') println()
+"# => Ok("[1, 2, 3]\n1\n3\nvec[int]\nvec[any]\nvec[any]\ntrue\n[[1], ['x']]\n['a', 'b', '', 'c']\n")
+    }
+
+    assert_same! {
+        vec_index_out_of_range: "[1, 2][2]" => Err("value error: index 2 out of range for vec of length 2")
+    }
+
+    assert_same! {
+        for_range: r#"
+total := new 0
+for i in 0 until(5) {
+    if i == 1 { continue }
+    if i == 4 { break }
+    total set(total get() + i)
+}
+total get() println()
+for i in 1 to(3) { i println() }
+v := [1, 2, 3, 4, 5]
+for i in (v len() - 2) until(v len()) { v[i] println() }
+"# => Ok("5\n1\n2\n3\n4\n5\n")
+    }
+
+    assert_same! {
+        for_iterator_protocol: r#"
+countdown := fn (n) {
+    (iter: fn (self) {
+        i := new n
+        (next: fn (it) {
+            k := i get()
+            if k > 0 {
+                i set(k - 1)
+                (k,)
+            } else {
+                ()
+            }
+        })
+    })
+}
+for k in countdown(3) { k println() }
+"# => Ok("3\n2\n1\n")
+    }
+
+    assert_same! {
+        for_return: r#"
+find := fn (xs, y) {
+    for x in xs {
+        if x == y { return true }
+    }
+    false
+}
+find([1, 2, 3], 2) println()
+find([1, 2, 3], 4) println()
+"# => Ok("true\nfalse\n")
+    }
+
+    assert_same! {
+        for_not_iterable: "for x in 1 {}" => Err("name error: int has no method 'iter'")
+    }
+
+    assert_same! {
+        switch_records: r#"
+area := fn (shape) {
+    switch shape {
+        (w: 0, ..) => 0
+        (w: w, h: h) => w * h
+        (r,) => r * r * 3
+        (a, b, ..) if a == b => a
+        (a, b, c) => a + b + c
+    }
+}
+area((w: 0, h: 5)) println()
+area((w: 2, h: 5)) println()
+area((2,)) println()
+area((4, 4, 9)) println()
+area((1, 2, 3)) println()
+"# => Ok("0\n10\n12\n4\n6\n")
+    }
+
+    assert_same! {
+        switch_in_loop: r#"
+total := new 0
+for x in [1, 2, 3, 4] {
+    switch x {
+        2 => { continue }
+        4 => { break }
+        _ => total set(total get() + x)
+    }
+}
+total get() println()
+"# => Ok("4\n")
+    }
+
+    assert_same! {
+        switch_capture: r#"
+f := switch (1, 2) { (a, b) => fn { a + b } }
+f() println()
+"# => Ok("3\n")
+    }
+
+    assert_same! {
+        switch_no_match: "switch (1, 'a') { (1, 'b') => 0, (x,) => x }"
+            => Err("match error: no switch arm matches (1, 'a')")
+    }
+
+    assert_same! {
+        switch_guard_not_bool: "switch 1 { x if x => 0 }" => Err("type error: expected 'bool', found 'int'")
+    }
+
+    assert_same! {
+        assert_pass: "x := 2\nassert x == 2\nassert x > 1, 'positive'\nassert true, undefined\n'ok' println()" => Ok("ok\n")
+    }
+
+    assert_same! {
+        assert_comparison: "x := 1\nassert x + 1 == 3" => Err("assertion failed: x + 1 == 3 (left: 2, right: 3)")
+    }
+
+    assert_same! {
+        assert_not_bool: "assert 1" => Err("type error: expected 'bool', found 'int'")
+    }
+
+    assert_same! {
+        nominal_union: r#"
+type Shape := Circle(r: int) | Rect(w: int, h: int) | Empty
+area := fn (shape) {
+    switch shape {
+        Circle(r) => r * r * 3
+        Rect(w: w, h: h) => w * h
+        Empty() => 0
+    }
+}
+area(Circle(2)) println()
+area(Rect(2, 5)) println()
+area(Empty) println()
+f'{Empty}' println()
+Rect(2, 5) typename() println()
+(Empty == ()) println()
+"# => Ok("12\n10\n0\nEmpty\nShape\nfalse\n")
+    }
+
+    assert_same! {
+        nominal_unit_variant_pattern: r#"
+type Shape := Circle(r: int) | Empty
+f := fn (s) {
+    switch s {
+        Empty => 'empty'
+        Circle(r) => 'circle'
+    }
+}
+f(Circle(1)) println()
+f(Empty) println()
+g := fn (s) {
+    switch s {
+        Empty => 'empty'
+        other => 'other'
+    }
+}
+g(Empty) println()
+g(1) println()
+"# => Ok("circle\nempty\nempty\nother\n")
+    }
+
+    assert_same! {
+        nominal_pattern_tag_identity: r#"
+type P := (x: int)
+q := P
+type P := (x: int)
+(q(1) == P(1)) println()
+switch q(1) {
+    P(x) => 'p'
+    _ => 'other'
+} println()
+switch P(1) {
+    P(x) => 'p'
+    _ => 'other'
+} println()
+"# => Ok("false\nother\np\n")
+    }
+
+    assert_same! {
+        nominal_unbound_tag: "B := 1\nswitch B { Aa(x) => 1 }" => Err("name error: Aa")
+    }
+
+    assert_same! {
+        record_type_error: "1 + (x: 1, y: 'a')" => Err("type error: expected 'int', found '(x: int, y: str)'")
+    }
+
+    assert_same! {
+        not_a_function: "(1, 2)()" => Err("type error: expected 'fn', found '(int, int)'")
+    }
+
+    assert_same! {
+        record_typename: "(x: 1, y: (1, 'a')) typename() println()" => Ok("(x: int, y: (int, str))\n")
+    }
+
+    assert_same! {
+        break_at_top_level: "break" => Err("unexpected break statement")
+    }
+
+    #[test]
+    fn traceback() {
+        let s = "f := fn (x) { x / 0 }\ng := fn { f(1) }\ng()";
+        for &engine in &[Engine::TreeWalking, Engine::Bytecode] {
+            let rt = Runtime::new();
+            let mut capsule = rt.capsule_builder().engine(engine).build();
+            let prog: ScriptProgram = capsule.parse_sourcecode(s).unwrap();
+            let err = capsule.eval(&prog).unwrap_err();
+            let frames: Vec<_> = err
+                .traceback()
+                .unwrap()
+                .frames()
+                .map(|f| {
+                    (
+                        f.name.to_string(),
+                        f.span.start().line,
+                        f.span.start().column,
+                    )
+                })
+                .collect();
+            assert_eq!(
+                frames,
+                vec![("f".to_string(), 2, 11), ("g".to_string(), 3, 1)],
+                "{:?}",
+                engine
+            );
+            let span = err.span().unwrap();
+            assert_eq!(span.start(), Position::new(1, 15), "{:?}", engine);
+        }
+    }
+
+    #[test]
+    fn assertion_span() {
+        let s = "x := 1\nassert x == 2   -- comment\n";
+        for &engine in &[Engine::TreeWalking, Engine::Bytecode] {
+            let rt = Runtime::new();
+            let mut capsule = rt.capsule_builder().engine(engine).build();
+            let prog: ScriptProgram = capsule.parse_sourcecode(s).unwrap();
+            let err = capsule.eval(&prog).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                "assertion failed: x == 2 (left: 1, right: 2)",
+                "{:?}",
+                engine
+            );
+            let span = err.span().unwrap();
+            assert_eq!(span.start(), Position::new(2, 1), "{:?}", engine);
+        }
+    }
+
+    #[test]
+    fn traceback_without_native_methods() {
+        let s = "g := fn { 'abc' println(1) }\nf := fn { g() }\nf()";
+        for &engine in &[Engine::TreeWalking, Engine::Bytecode] {
+            let rt = Runtime::new();
+            let mut capsule = rt.capsule_builder().engine(engine).build();
+            let prog: ScriptProgram = capsule.parse_sourcecode(s).unwrap();
+            let err = capsule.eval(&prog).unwrap_err();
+            let frames: Vec<_> = err
+                .traceback()
+                .unwrap()
+                .frames()
+                .map(|f| f.name.to_string())
+                .collect();
+            assert_eq!(frames, ["g", "f"], "{:?}", engine);
+        }
+    }
+}
@@ -26,6 +26,7 @@ mod inst;
 pub mod capsule;
 pub mod error;
 pub mod runtime;
+pub mod testing;
 
 pub use crate::capsule::Capsule;
 pub use crate::error::{Error, Fallible};
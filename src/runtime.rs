@@ -10,6 +10,7 @@ use crate::{
     environment::Package,
     error::{Error, Fallible},
     eval::Evaluate,
+    testing::TestRunner,
 };
 
 pub struct Runtime {
@@ -53,12 +54,18 @@ impl Runtime {
         CapsuleBuilder::new(self.context())
     }
 
+    pub fn test_runner(&self) -> TestRunner<'_> {
+        TestRunner::new(self)
+    }
+
     pub fn execute(&self, path: impl AsRef<Path>) -> Fallible<()> {
         let path = path.as_ref();
         let input = std::fs::read_to_string(path).map_err(|_| Error::load(path))?;
         let mut capsule = self.root_capsule();
-        let prog: ScriptProgram = capsule.parse_sourcecode(&input)?;
-        prog.eval(&mut capsule)?;
+        let result = capsule
+            .parse_sourcecode(&input)
+            .and_then(|prog: ScriptProgram| prog.eval(&mut capsule));
+        result.map_err(|e| e.with_path(path))?;
         Ok(())
     }
 }
@@ -83,6 +90,45 @@ mod test {
         assert_eq!(std::str::from_utf8(&out).unwrap(), "Hello, world!\n");
     }
 
+    #[test]
+    fn example() {
+        let s = include_str!("../tests/example.n");
+        let rt = Runtime::new();
+        let mut out = Vec::new();
+        {
+            let w = Box::new(io::Cursor::new(&mut out));
+            let mut capsule = Capsule::new(rt.context(), w);
+            let prog: ScriptProgram = capsule.parse_sourcecode(s).unwrap();
+            capsule.eval(&prog).unwrap();
+            capsule.eval("main()").unwrap();
+        }
+        assert_eq!(std::str::from_utf8(&out).unwrap(), "Hello?\n");
+    }
+
+    #[test]
+    fn traceback() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/traceback.n");
+        let rt = Runtime::new();
+        let err = rt.execute(path).unwrap_err();
+        assert_eq!(err.to_string(), "name error: y");
+        let traceback = err.traceback().unwrap();
+        assert_eq!(
+            traceback.to_string(),
+            format!("  at inner ({0}:5:5)\n  at outer ({0}:7:1)\n", path)
+        );
+        assert!(traceback
+            .frames()
+            .all(|frame| frame.path.as_deref() == Some(Path::new(path))));
+        let source = include_str!("../tests/traceback.n");
+        assert_eq!(
+            err.report(source).to_string(),
+            format!(
+                "name error: y\n --> {0}:2:9\n  |\n2 |     x + y\n  |         ^\n{1}",
+                path, traceback
+            )
+        );
+    }
+
     #[cfg(feature = "deserialize")]
     #[test]
     fn helloworld_yaml() {
@@ -0,0 +1,246 @@
+//! Running the `test` declarations of packages.
+
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use urashima_ast::{
+    program::{PackageProgram, ScriptProgram},
+    span::Spanned,
+    statement::impls::Statement,
+};
+
+use crate::{
+    capsule::Engine,
+    error::{AssertionFailure, ControlFlow, Error, Fallible},
+    eval::Evaluate,
+    runtime::Runtime,
+};
+
+/// Runs the tests of a package, each in a capsule of its own with the bindings of the package.
+pub struct TestRunner<'r> {
+    runtime: &'r Runtime,
+    engine: Engine,
+    filter: Option<String>,
+}
+
+impl<'r> TestRunner<'r> {
+    pub(crate) fn new(runtime: &'r Runtime) -> Self {
+        TestRunner {
+            runtime,
+            engine: Engine::default(),
+            filter: None,
+        }
+    }
+
+    pub fn engine(mut self, engine: Engine) -> Self {
+        self.engine = engine;
+        self
+    }
+
+    /// Run only the tests whose names contain the pattern.
+    pub fn filter(mut self, pattern: impl Into<String>) -> Self {
+        self.filter = Some(pattern.into());
+        self
+    }
+
+    /// Names of the tests declared in the source code of a package, in order of declaration
+    pub fn discover(&self, source: &str) -> Fallible<Vec<String>> {
+        let prog: PackageProgram = self.runtime.root_capsule().parse_sourcecode(source)?;
+        Ok(prog.tests.into_iter().map(|test| test.name.node).collect())
+    }
+
+    pub fn run(&self, path: impl AsRef<Path>) -> Fallible<TestReport> {
+        let path = path.as_ref();
+        let input = std::fs::read_to_string(path).map_err(|_| Error::load(path))?;
+        let mut report = self.run_source(&input).map_err(|e| e.with_path(path))?;
+        for result in &mut report.results {
+            result.error = result.error.take().map(|e| e.with_path(path));
+        }
+        Ok(report)
+    }
+
+    pub fn run_source(&self, source: &str) -> Fallible<TestReport> {
+        let mut report = TestReport::default();
+        for (idx, name) in self.discover(source)?.into_iter().enumerate() {
+            match &self.filter {
+                Some(pattern) if !name.contains(pattern.as_str()) => report.filtered_out += 1,
+                _ => report.results.push(self.run_test(source, idx, name)),
+            }
+        }
+        Ok(report)
+    }
+
+    /// The package is evaluated again for each test, so that tests don't share any state.
+    fn run_test(&self, source: &str, idx: usize, name: String) -> TestResult {
+        let mut out = Vec::new();
+        let start = Instant::now();
+        let result = {
+            let mut capsule = self
+                .runtime
+                .capsule_builder()
+                .stdout(Box::new(io::Cursor::new(&mut out)))
+                .engine(self.engine)
+                .build();
+            let mut prog: PackageProgram = match capsule.parse_sourcecode(source) {
+                Ok(prog) => prog,
+                Err(e) => return TestResult::new(name, String::new(), start.elapsed(), Some(e)),
+            };
+            let test = prog.tests.swap_remove(idx);
+            prog.eval(&mut capsule).and_then(|()| {
+                let body = Spanned::new(test.body.span, Statement::Expr(test.body));
+                let body = ScriptProgram {
+                    statements: vec![body],
+                    tests: vec![],
+                };
+                body.eval(&mut capsule)
+            })
+        };
+        let duration = start.elapsed();
+        let error = match result {
+            Err(e) if !matches!(e.as_control_flow(), Some(ControlFlow::Return(_))) => Some(e),
+            _ => None,
+        };
+        let stdout = String::from_utf8_lossy(&out).into_owned();
+        TestResult::new(name, stdout, duration, error)
+    }
+}
+
+#[derive(Default)]
+pub struct TestReport {
+    /// Results of the tests which have been run, in order of declaration
+    pub results: Vec<TestResult>,
+    /// Number of the tests which didn't match the filter
+    pub filtered_out: usize,
+}
+
+impl TestReport {
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|r| r.passed()).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.results.len() - self.passed()
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.results.iter().all(TestResult::passed)
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.results.iter().map(|r| r.duration).sum()
+    }
+}
+
+pub struct TestResult {
+    pub name: String,
+    /// Output printed by the test
+    pub stdout: String,
+    pub duration: Duration,
+    /// The error which failed the test
+    pub error: Option<Error>,
+}
+
+impl TestResult {
+    fn new(name: String, stdout: String, duration: Duration, error: Option<Error>) -> Self {
+        TestResult {
+            name,
+            stdout,
+            duration,
+            error,
+        }
+    }
+
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// The failed assertion, if the test failed by an `assert` statement
+    pub fn assertion(&self) -> Option<&AssertionFailure> {
+        self.error.as_ref()?.as_assertion()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SOURCE: &str = r#"
+double := fn (x) { x * 2 }
+counter := new 0
+
+test 'double' {
+    counter set(counter get() + 1)
+    double(21) println()
+    assert double(21) == 42
+}
+
+test 'double again' {
+    assert counter get() == 0, 'tests should not share state'
+    return
+    assert false
+}
+
+test 'double wrong' {
+    'checking' println()
+    assert double(2) == 5, 'two'
+}
+
+test 'broken' {
+    undefined()
+}
+"#;
+
+    #[test]
+    fn run_tests() {
+        let rt = Runtime::new();
+        for &engine in &[Engine::TreeWalking, Engine::Bytecode] {
+            let report = rt.test_runner().engine(engine).run_source(SOURCE).unwrap();
+            let names: Vec<_> = report.results.iter().map(|r| &r.name[..]).collect();
+            assert_eq!(names, ["double", "double again", "double wrong", "broken"]);
+            assert_eq!((report.passed(), report.failed()), (2, 2));
+            assert!(!report.is_success());
+
+            let [double, again, wrong, broken] = &report.results[..] else {
+                unreachable!()
+            };
+            assert_eq!(double.stdout, "42\n");
+            assert!(again.passed());
+            assert_eq!(wrong.stdout, "checking\n");
+            let assertion = wrong.assertion().unwrap();
+            assert_eq!(assertion.expr, "double(2) == 5");
+            assert_eq!(assertion.operands, Some(("4".to_string(), "5".to_string())));
+            assert_eq!(assertion.message.as_deref(), Some("two"));
+            assert!(broken.assertion().is_none());
+            assert!(!broken.passed());
+        }
+    }
+
+    #[test]
+    fn filter_tests() {
+        let rt = Runtime::new();
+        let runner = rt.test_runner().filter("double");
+        assert_eq!(runner.discover(SOURCE).unwrap().len(), 4);
+        let report = runner.run_source(SOURCE).unwrap();
+        assert_eq!(report.results.len(), 3);
+        assert_eq!(report.filtered_out, 1);
+    }
+
+    #[test]
+    fn no_tests_on_load() {
+        let rt = Runtime::new();
+        let mut out = Vec::new();
+        {
+            let mut capsule = rt
+                .capsule_builder()
+                .stdout(Box::new(io::Cursor::new(&mut out)))
+                .build();
+            let prog: PackageProgram = capsule.parse_sourcecode(SOURCE).unwrap();
+            capsule.eval(&prog).unwrap();
+            let script: ScriptProgram = capsule.parse_sourcecode(SOURCE).unwrap();
+            assert_eq!(script.tests.len(), 4);
+            capsule.eval(&script).unwrap();
+        }
+        assert!(out.is_empty());
+    }
+}
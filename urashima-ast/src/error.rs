@@ -3,11 +3,15 @@ use core::fmt;
 use failure::{Backtrace, Context, Fail};
 use urashima_util::Symbol;
 
-use crate::parser::Rule;
+use crate::{
+    parser::Rule,
+    span::{Position, Span},
+};
 
 #[derive(Debug)]
 pub struct Error {
     inner: Context<ErrorKind>,
+    span: Option<Span>,
 }
 
 impl Fail for Error {
@@ -39,10 +43,23 @@ impl Error {
         ErrorKind::UnexpectedRule { expected, found }.into()
     }
 
+    pub(crate) fn invalid_literal(text: &str) -> Self {
+        ErrorKind::Parse(format!("invalid literal '{}'", text)).into()
+    }
+
+    pub(crate) fn duplicate_label(label: Symbol) -> Self {
+        ErrorKind::DuplicateLabel { label }.into()
+    }
+
     pub(crate) fn unimplemented() -> Error {
         ErrorKind::Unimplemented.into()
     }
 
+    /// Location of the source code where the error was found
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+
     pub(crate) fn is_unexpected(&self) -> bool {
         if let ErrorKind::UnexpectedRule { .. } = self.inner.get_context() {
             true
@@ -63,6 +80,12 @@ enum ErrorKind {
     #[fail(display = "unimplemented")]
     Unimplemented,
 
+    #[fail(
+        display = "All labels in the record should be unique: '{}' is used twice",
+        label
+    )]
+    DuplicateLabel { label: Symbol },
+
     #[fail(display = "name error: {}", name)]
     Name { name: Symbol },
 
@@ -74,19 +97,72 @@ impl From<ErrorKind> for Error {
     fn from(kind: ErrorKind) -> Self {
         Error {
             inner: Context::new(kind),
+            span: None,
         }
     }
 }
 
 impl From<Context<ErrorKind>> for Error {
     fn from(inner: Context<ErrorKind>) -> Self {
-        Error { inner }
+        Error { inner, span: None }
     }
 }
 
 impl<R: pest::RuleType> From<pest::error::Error<R>> for Error {
     fn from(err: pest::error::Error<R>) -> Self {
-        ErrorKind::Parse(err.to_string()).into()
+        use pest::error::{ErrorVariant, InputLocation, LineColLocation};
+
+        let message = match err.variant {
+            ErrorVariant::ParsingError {
+                positives,
+                negatives,
+            } => match (negatives.is_empty(), positives.is_empty()) {
+                (false, false) => format!(
+                    "unexpected {}; expected {}",
+                    enumerate(&negatives),
+                    enumerate(&positives)
+                ),
+                (false, true) => format!("unexpected {}", enumerate(&negatives)),
+                (true, false) => format!("expected {}", enumerate(&positives)),
+                (true, true) => "unknown parsing error".to_owned(),
+            },
+            ErrorVariant::CustomError { message } => message,
+        };
+        let (start, end) = match err.location {
+            InputLocation::Pos(pos) => (pos, pos),
+            InputLocation::Span(span) => span,
+        };
+        let ((line, column), (end_line, end_column)) = match err.line_col {
+            LineColLocation::Pos(pos) => (pos, pos),
+            LineColLocation::Span(start, end) => (start, end),
+        };
+        let span = Span::new(
+            Position {
+                line,
+                column,
+                pos: Some(start),
+            },
+            Position {
+                line: end_line,
+                column: end_column,
+                pos: Some(end),
+            },
+        );
+        Error {
+            inner: Context::new(ErrorKind::Parse(message)),
+            span: Some(span),
+        }
+    }
+}
+
+fn enumerate<R: fmt::Debug>(rules: &[R]) -> String {
+    match rules {
+        [] => String::new(),
+        [rule] => format!("{:?}", rule),
+        [init @ .., last] => {
+            let init: Vec<_> = init.iter().map(|r| format!("{:?}", r)).collect();
+            format!("{}, or {:?}", init.join(", "), last)
+        }
     }
 }
 
@@ -84,7 +84,7 @@ impl Print for InvokeExpression {
             f,
             "{} {}({})",
             f.display(&self.receiver),
-            self.method.node,
+            print::Name(&self.method.node),
             f.display_seq(&self.arguments[..], ", "),
         )
     }
@@ -2,10 +2,13 @@
 use serde_derive_state::DeserializeState;
 
 use super::{BlockExpression, ExprArena, ExprIndex};
+use urashima_util::Symbol;
+
 use crate::{
     error::Fallible,
     find::Find,
-    parser::{Pairs, Parse, Rule},
+    parser::{name_of, Pairs, Parse, Rule},
+    print::{self, Print},
     span::{Position, Span, Spanned},
 };
 
@@ -37,6 +40,21 @@ pub struct LoopExpression {
     pub blk: BlockExpression,
 }
 
+/// `for name in iterable { ... }`
+#[derive(Clone)]
+#[cfg_attr(any(feature = "dev", test), derive(Debug))]
+#[cfg_attr(feature = "deserialize", derive(DeserializeState))]
+#[cfg_attr(feature = "deserialize", serde(deserialize_state = "ExprArena"))]
+pub struct ForExpression {
+    #[cfg_attr(feature = "deserialize", serde(skip))]
+    for_keyword: Span,
+    pub binding: Spanned<Symbol>,
+    #[cfg_attr(feature = "deserialize", serde(state))]
+    pub iterable: Spanned<ExprIndex>,
+    #[cfg_attr(feature = "deserialize", serde(state))]
+    pub blk: BlockExpression,
+}
+
 impl Parse for IfExpression {
     const RULE: Rule = Rule::if_expression;
 
@@ -86,6 +104,29 @@ impl Parse for LoopExpression {
     }
 }
 
+impl Parse for ForExpression {
+    const RULE: Rule = Rule::for_expression;
+
+    fn from_pairs<'i>(
+        arena: &mut ExprArena,
+        _span: pest::Span<'i>,
+        mut pairs: Pairs<'i>,
+    ) -> Fallible<Self> {
+        let for_keyword = Span::from(&pairs.next().expect("unreachable").as_span());
+        let name = pairs.next().expect("unreachable");
+        let binding = Spanned::new(&name.as_span(), name_of(&name));
+        pairs.next().expect("unreachable"); // in
+        let iterable = Parse::from_pair(arena, pairs.next().expect("unreachable"))?;
+        let blk = Parse::from_pair(arena, pairs.next().expect("unreachable"))?;
+        Ok(ForExpression {
+            for_keyword,
+            binding,
+            iterable,
+            blk,
+        })
+    }
+}
+
 impl Find for IfExpression {
     fn find_span(&self, pos: Position, arena: &ExprArena) -> Option<Span> {
         log::debug!("find_span(IfExpression)");
@@ -104,6 +145,29 @@ impl Find for LoopExpression {
     }
 }
 
+impl Find for ForExpression {
+    fn find_span(&self, pos: Position, arena: &ExprArena) -> Option<Span> {
+        log::debug!("find_span(ForExpression)");
+        self.for_keyword
+            .find_span(pos, arena)
+            .or_else(|| self.binding.span.find_span(pos, arena))
+            .or_else(|| self.iterable.find_span(pos, arena))
+            .or_else(|| self.blk.find_span(pos, arena))
+    }
+}
+
+impl Print for ForExpression {
+    fn fmt(&self, f: &mut print::Formatter<'_>) -> print::Result {
+        write!(
+            f,
+            "for {} in {} ",
+            print::Name(&self.binding.node),
+            f.display(&self.iterable.node)
+        )?;
+        Print::fmt(&self.blk, f)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -158,4 +222,25 @@ mod test {
             }
         );
     }
+
+    #[test]
+    fn for_in() {
+        let mut arena = ExprArena::new();
+        assert_pat!(
+            Expression::from_str(&mut arena, r#"for x in xs filter(f) { x println() }"#).unwrap(),
+            Spanned { node: For(ForExpression { binding, iterable, blk, .. }), .. } => {
+                assert_eq!(&binding.node, "x");
+                assert_pat!(&arena[iterable.node].node, Invoke(..) => {});
+                assert!(blk.returns().is_some());
+            }
+        );
+        assert!(Expression::from_str(&mut arena, "for in in xs {}").is_err());
+
+        let expr =
+            Expression::from_str(&mut arena, "for i in 0 until(3) {\n    i println()\n}").unwrap();
+        assert_eq!(
+            expr.display(&arena).to_string(),
+            "for i in 0 until(3) {\n    i println()\n}"
+        );
+    }
 }
@@ -9,7 +9,7 @@ use super::{BlockExpression, ExprArena};
 use crate::{
     error::Fallible,
     find::Find,
-    parser::{Pairs, Parse, Rule},
+    parser::{name_of, Pairs, Parse, Rule},
     print::{self, Print},
     span::{Position, Span, Spanned},
 };
@@ -54,7 +54,7 @@ impl Parse for FunctionExpression {
             match item.as_rule() {
                 Rule::fn_param => {
                     parameters.push(Parameter {
-                        name: Spanned::new(&item.as_span(), item.as_str().into()),
+                        name: Spanned::new(&item.as_span(), name_of(&item)),
                     });
                 }
                 Rule::grouping_brace => {
@@ -92,7 +92,7 @@ impl<'a> Print for FunctionExpression {
 
 impl<'a> Print for Parameter {
     fn fmt(&self, f: &mut print::Formatter<'_>) -> print::Result {
-        f.write_str(&self.name.node)
+        write!(f, "{}", print::Name(&self.name.node))
     }
 }
 
@@ -2,21 +2,24 @@ use std::cell::RefCell;
 
 use lazy_static::lazy_static;
 use pest::prec_climber::{Assoc, Operator, PrecClimber};
-use urashima_util::Symbol;
+use urashima_util::{
+    num::{parse_decimal, parse_integer, Decimal, Int, Rat},
+    Symbol,
+};
 
 #[cfg(feature = "deserialize")]
 use serde_derive_state::DeserializeState;
 
 use crate::{
     error::{Error, Fallible},
-    parser::{Pairs, Parse, Rule},
+    parser::{name_of, string_of, Pairs, Parse, Rule},
     print::{self, Print},
     span::{Position, Span, Spanned},
 };
 
 use super::{
-    BlockExpression, CallExpression, ExprArena, ExprIndex, FunctionExpression, IfExpression,
-    InvokeExpression, LoopExpression,
+    BlockExpression, CallExpression, ExprArena, ExprIndex, ForExpression, FunctionExpression,
+    IfExpression, InvokeExpression, LoopExpression, SwitchExpression,
 };
 
 #[derive(Clone)]
@@ -27,11 +30,20 @@ pub enum Expression {
     // Atomic
     False,
     True,
-    Integral(i64),
+    Integral(
+        #[cfg_attr(
+            feature = "deserialize",
+            serde(deserialize_with = "urashima_util::num::deserialize_int")
+        )]
+        Int,
+    ),
+    Rational(Rat),
     Str(String),
+    Format(#[cfg_attr(feature = "deserialize", serde(state))] Vec<FormatPart>),
     Name(Symbol),
 
     Record(#[cfg_attr(feature = "deserialize", serde(state))] Vec<(Symbol, ExprIndex)>),
+    Vec(#[cfg_attr(feature = "deserialize", serde(state))] Vec<ExprIndex>),
     Block(#[cfg_attr(feature = "deserialize", serde(state))] BlockExpression),
     Fn(#[cfg_attr(feature = "deserialize", serde(state))] FunctionExpression),
 
@@ -42,12 +54,32 @@ pub enum Expression {
         #[cfg_attr(feature = "deserialize", serde(state))] ExprIndex,
         #[cfg_attr(feature = "deserialize", serde(state))] ExprIndex,
     ),
+    Field(
+        #[cfg_attr(feature = "deserialize", serde(state))] ExprIndex,
+        Spanned<Symbol>,
+    ),
+    Index(
+        #[cfg_attr(feature = "deserialize", serde(state))] ExprIndex,
+        #[cfg_attr(feature = "deserialize", serde(state))] ExprIndex,
+    ),
     Call(#[cfg_attr(feature = "deserialize", serde(state))] CallExpression),
     Invoke(#[cfg_attr(feature = "deserialize", serde(state))] InvokeExpression),
 
     // Control flow
     If(#[cfg_attr(feature = "deserialize", serde(state))] IfExpression),
     Loop(#[cfg_attr(feature = "deserialize", serde(state))] LoopExpression),
+    For(#[cfg_attr(feature = "deserialize", serde(state))] ForExpression),
+    Switch(#[cfg_attr(feature = "deserialize", serde(state))] SwitchExpression),
+}
+
+/// A piece of a format string: either literal text or an embedded expression.
+#[derive(Clone)]
+#[cfg_attr(any(feature = "dev", test), derive(Debug))]
+#[cfg_attr(feature = "deserialize", derive(DeserializeState))]
+#[cfg_attr(feature = "deserialize", serde(deserialize_state = "ExprArena"))]
+pub enum FormatPart {
+    Str(String),
+    Expr(#[cfg_attr(feature = "deserialize", serde(state))] ExprIndex),
 }
 
 impl Expression {
@@ -113,15 +145,52 @@ impl<'a> Print for Expression {
             False => f.write_str("false"),
             True => f.write_str("true"),
             Integral(i) => write!(f, "{}", i),
-            Str(s) => write!(f, "{}", s),
-            Name(name) => f.write_str(&name),
+            Rational(r) => write!(f, "{}", Decimal(r)),
+            Str(s) => write!(f, "{}", print::Str(s)),
+            Format(parts) => {
+                f.write_str("f'")?;
+                for part in parts {
+                    match part {
+                        FormatPart::Str(s) => write!(f, "{}", print::FormatText(s))?,
+                        FormatPart::Expr(expr) => write!(f, "{{{}}}", f.display(expr))?,
+                    }
+                }
+                f.write_str("'")
+            }
+            Name(name) => write!(f, "{}", print::Name(name)),
             Block(expr) => Print::fmt(expr, f),
             Fn(expr) => Print::fmt(expr, f),
 
             Infix(op, a, b) => write!(f, "{} {} {}", f.display(a), op.node, f.display(b)),
             New(expr) => write!(f, "new {}", f.display(expr)),
+            Field(expr, label) if label.node.bytes().all(|b| b.is_ascii_digit()) => {
+                write!(f, "{}.{}", f.display(expr), label.node)
+            }
+            Field(expr, label) => write!(f, "{}.{}", f.display(expr), print::Name(&label.node)),
+            Index(expr, index) => write!(f, "{}[{}]", f.display(expr), f.display(index)),
             Call(expr) => Print::fmt(expr, f),
             Invoke(expr) => Print::fmt(expr, f),
+            For(expr) => Print::fmt(expr, f),
+            Switch(expr) => Print::fmt(expr, f),
+
+            Record(fields) => {
+                f.write_str("(")?;
+                for (i, (label, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    if label.parse() != Ok(i) {
+                        write!(f, "{}: ", print::Name(label))?;
+                    }
+                    write!(f, "{}", f.display(value))?;
+                }
+                if fields.len() == 1 {
+                    f.write_str(",")?;
+                }
+                f.write_str(")")
+            }
+
+            Vec(items) => write!(f, "[{}]", f.display_seq(items, ", ")),
 
             _ => unimplemented!(),
         }
@@ -188,12 +257,22 @@ fn parse_operand_expression(
                 _ => unreachable!(),
             },
             Rule::numeric => {
-                let num = head.as_str().parse()?;
-                Expression::Integral(num)
-            }
-            Rule::string => {
                 let text = head.as_str();
-                Expression::Str(text.to_string())
+                if text.contains('.') {
+                    let num = parse_decimal(text).ok_or_else(|| Error::invalid_literal(text))?;
+                    Expression::Rational(num)
+                } else {
+                    let num = parse_integer(text).ok_or_else(|| Error::invalid_literal(text))?;
+                    Expression::Integral(num)
+                }
+            }
+            Rule::string | Rule::raw_string | Rule::multiline_string => {
+                Expression::Str(string_of(&head)?)
+            }
+            Rule::format_string => parse_format_string(arena, head.into_inner())?,
+            Rule::record_expression => parse_record_expression(arena, head.into_inner())?,
+            Rule::vec_expression => {
+                Expression::Vec(parse_call_arguments(arena, head.into_inner())?)
             }
             Rule::grouping_paren => {
                 Expression::from_pair(arena, head.into_inner().next().unwrap())?
@@ -208,7 +287,7 @@ fn parse_operand_expression(
                     FunctionExpression::from_pairs(&mut *arena, head.as_span(), head.into_inner())?;
                 Expression::Fn(expr)
             }
-            Rule::name => Expression::Name(head.as_str().into()),
+            Rule::name => Expression::Name(name_of(&head)),
 
             Rule::if_expression => {
                 let expr =
@@ -220,6 +299,22 @@ fn parse_operand_expression(
                     LoopExpression::from_pairs(&mut *arena, head.as_span(), head.into_inner())?;
                 Expression::Loop(expr)
             }
+            Rule::for_expression => {
+                let expr =
+                    ForExpression::from_pairs(&mut *arena, head.as_span(), head.into_inner())?;
+                Expression::For(expr)
+            }
+            Rule::switch_expression => {
+                let expr =
+                    SwitchExpression::from_pairs(&mut *arena, head.as_span(), head.into_inner())?;
+                Expression::Switch(expr)
+            }
+            Rule::new_expression => {
+                let operand = head.into_inner().nth(1).unwrap();
+                let expr =
+                    parse_operand_expression(arena, operand.as_span(), operand.into_inner())?;
+                Expression::New(arena.insert(expr))
+            }
             _ => {
                 return Err(Error::unimplemented());
             }
@@ -237,6 +332,21 @@ fn parse_operand_expression(
                 let node = Expression::call(arena.insert(expr), args);
                 expr = Spanned::new(span, node);
             }
+            Rule::field_access => {
+                let label = rest.into_inner().next().unwrap();
+                let span = Span::enclosing(expr.span, Span::from(&label.as_span()));
+                let text = match label.as_rule() {
+                    Rule::name => name_of(&label),
+                    _ => label.as_str().into(),
+                };
+                let label = Spanned::new(&label.as_span(), text);
+                expr = Spanned::new(span, Expression::Field(arena.insert(expr), label));
+            }
+            Rule::index_access => {
+                let span = Span::enclosing(expr.span, Span::from(&rest.as_span()));
+                let index = ExprIndex::from_pair(&mut *arena, rest.into_inner().next().unwrap())?;
+                expr = Spanned::new(span, Expression::Index(arena.insert(expr), index));
+            }
             Rule::method_call => {
                 let end_pos = rest.as_span().end_pos();
                 let (method, args) = parse_method_call(&mut *arena, rest.into_inner())?;
@@ -250,6 +360,50 @@ fn parse_operand_expression(
     Ok(expr)
 }
 
+/// Parse the fields of a record literal. Fields without labels are labeled by their positions.
+fn parse_record_expression(arena: &mut ExprArena, pairs: Pairs<'_>) -> Fallible<Expression> {
+    let mut fields = vec![];
+    let mut label = None;
+    for pair in pairs {
+        match pair.as_rule() {
+            Rule::name => label = Some(name_of(&pair)),
+            Rule::expression => {
+                let label = label
+                    .take()
+                    .unwrap_or_else(|| fields.len().to_string().into());
+                fields.push((label, ExprIndex::from_pair(&mut *arena, pair)?));
+            }
+            Rule::record_field => {
+                let mut inner = pair.into_inner().collect::<Vec<_>>();
+                let value = ExprIndex::from_pair(&mut *arena, inner.pop().unwrap())?;
+                let label = match inner.pop() {
+                    Some(name) => name_of(&name),
+                    None => fields.len().to_string().into(),
+                };
+                fields.push((label, value));
+            }
+            _ => unreachable!(),
+        }
+    }
+    Ok(Expression::Record(fields))
+}
+
+fn parse_format_string(arena: &mut ExprArena, pairs: Pairs<'_>) -> Fallible<Expression> {
+    let mut parts = vec![];
+    for pair in pairs {
+        let part = match pair.as_rule() {
+            Rule::format_text => FormatPart::Str(string_of(&pair)?),
+            Rule::format_hole => {
+                let expr = pair.into_inner().next().unwrap();
+                FormatPart::Expr(ExprIndex::from_pair(&mut *arena, expr)?)
+            }
+            _ => unreachable!(),
+        };
+        parts.push(part);
+    }
+    Ok(Expression::Format(parts))
+}
+
 fn parse_call_arguments(arena: &mut ExprArena, pairs: Pairs<'_>) -> Fallible<Vec<ExprIndex>> {
     pairs
         .map(|rest| match rest.as_rule() {
@@ -267,8 +421,11 @@ fn parse_method_call(
         if name.as_rule() != Rule::name || args.as_rule() != Rule::call_arguments {
             unreachable!();
         }
-        let method_name = Spanned::new(&name.as_span(), name.as_str().into());
-        let arguments = Spanned::new(&args.as_span(), parse_call_arguments(arena, pairs)?);
+        let method_name = Spanned::new(&name.as_span(), name_of(&name));
+        let arguments = Spanned::new(
+            &args.as_span(),
+            parse_call_arguments(arena, args.into_inner())?,
+        );
         Ok((method_name, arguments))
     } else {
         unreachable!()
@@ -278,7 +435,7 @@ fn parse_method_call(
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::expr::ExprArena;
+    use crate::{expr::ExprArena, find::Find, program::ScriptProgram};
 
     #[test]
     fn atomic_true() {
@@ -298,6 +455,25 @@ mod test {
         );
     }
 
+    #[test]
+    fn atomic_decimal() {
+        let mut arena = ExprArena::new();
+        let expr = Expression::from_str(&mut arena, "12.50").unwrap();
+        assert_eq!(format!("{}", expr.display(&arena)), "12.5");
+        assert_pat!(
+            expr,
+            Expression::Rational(r) => {
+                assert_eq!(r, Rat::new(25.into(), 2.into()));
+            }
+        );
+        assert_pat!(
+            Expression::from_str(&mut arena, "0.125").unwrap(),
+            Expression::Rational(r) => {
+                assert_eq!(r, Rat::new(1.into(), 8.into()));
+            }
+        );
+    }
+
     #[test]
     fn atomic_str_simple_1() {
         let mut arena = ExprArena::new();
@@ -320,6 +496,96 @@ mod test {
         );
     }
 
+    #[test]
+    fn str_escapes() {
+        let mut arena = ExprArena::new();
+        let cases = &[
+            (r#"'a\nb\tc'"#, "a\nb\tc"),
+            (r#""it's \"quoted\"""#, "it's \"quoted\""),
+            (r#"'\\ \' \0'"#, "\\ ' \0"),
+            (r#"'\u{48}\u{1F600}'"#, "H\u{1F600}"),
+            (r#"r'C:\path\n'"#, "C:\\path\\n"),
+            ("'''\nfirst\n  'second'\n'''", "first\n  'second'\n"),
+            (r#"""" "a" \t""""#, " \"a\" \t"),
+        ];
+        for (code, expected) in cases {
+            assert_pat!(
+                Expression::from_str(&mut arena, code).unwrap(),
+                Expression::Str(s) => {
+                    assert_eq!(s, *expected);
+                }
+            );
+        }
+        assert!(Expression::from_str(&mut arena, r"'\q'").is_err());
+        assert!(Expression::from_str(&mut arena, r"'\u{D800}'").is_err());
+        assert!(Expression::from_str(&mut arena, "'a\nb'").is_err());
+    }
+
+    #[test]
+    fn str_print_roundtrip() {
+        let mut arena = ExprArena::new();
+        let cases = &[
+            ("'plain'", "'plain'"),
+            (r#""it's""#, r"'it\'s'"),
+            ("'''\nline 1\nline 2'''", r"'line 1\nline 2'"),
+            (r"r'\d+'", r"'\\d+'"),
+            (r"'\u{7}'", r"'\u{7}'"),
+        ];
+        for (code, printed) in cases {
+            let expr = Expression::from_str(&mut arena, code).unwrap();
+            let output = format!("{}", expr.display(&arena));
+            assert_eq!(output, *printed);
+            let reparsed = Expression::from_str(&mut arena, &output).unwrap();
+            assert_pat!((&expr, &reparsed), (Expression::Str(a), Expression::Str(b)) => {
+                assert_eq!(a, b);
+            });
+        }
+    }
+
+    #[test]
+    fn format_string() {
+        let mut arena = ExprArena::new();
+        let cases = &[
+            ("f'Hello, {name}!'", "f'Hello, {name}!'"),
+            ("f\"{a} + {b} = {a + b}\"", "f'{a} + {b} = {a + b}'"),
+            (
+                "f'{{literal}} {x.0 to_str()}\\n'",
+                "f'{{literal}} {x.0 to_str()}\\n'",
+            ),
+            ("f'{ f'{1}' }'", "f'{f'{1}'}'"),
+            ("f''", "f''"),
+        ];
+        for (code, printed) in cases {
+            let expr = Expression::from_str(&mut arena, code).unwrap();
+            assert_eq!(format!("{}", expr.display(&arena)), *printed);
+        }
+        assert_pat!(
+            Expression::from_str(&mut arena, "f'{{{x}}}'").unwrap(),
+            Expression::Format(parts) => {
+                assert_pat!(&parts[..], [FormatPart::Str(a), FormatPart::Expr(_), FormatPart::Str(b)] => {
+                    assert_eq!(a, "{");
+                    assert_eq!(b, "}");
+                });
+            }
+        );
+        for code in &["f'{'", "f'}'", "f'{}'"] {
+            assert!(ScriptProgram::from_str(&mut arena, code).is_err());
+        }
+    }
+
+    #[test]
+    fn format_string_find() {
+        let mut arena = ExprArena::new();
+        let idx = ExprIndex::from_str(&mut arena, "f'a {xy} b'").unwrap();
+        let hole = assert_pat!(&arena[idx].node, Expression::Format(parts) => {
+            assert_pat!(&parts[1], FormatPart::Expr(hole) => { *hole })
+        });
+        let found = arena[idx].find_span(Position::new(1, 7), &arena);
+        assert_eq!(found, Some(arena[hole].span));
+        let found = arena[idx].find_span(Position::new(1, 3), &arena);
+        assert_eq!(found, Some(arena[idx].span));
+    }
+
     #[test]
     fn atomic_fn_simple_1() {
         let mut arena = ExprArena::new();
@@ -344,4 +610,104 @@ mod test {
             }
         );
     }
+
+    #[test]
+    fn invoke_quoted_name() {
+        let mut arena = ExprArena::new();
+        let expr = Expression::from_str(&mut arena, "a `+`(1)").unwrap();
+        assert_eq!(format!("{}", expr.display(&arena)), "a `+`(1)");
+        assert_pat!(
+            expr,
+            Expression::Invoke(InvokeExpression { method, arguments, .. }) => {
+                assert_eq!(&method.node, "+");
+                assert_eq!(arguments.node.len(), 1);
+            }
+        );
+    }
+
+    #[test]
+    fn new_expression() {
+        let mut arena = ExprArena::new();
+        let expr = Expression::from_str(&mut arena, "new count get()").unwrap();
+        assert_eq!(format!("{}", expr.display(&arena)), "new count get()");
+        assert_pat!(
+            expr,
+            Expression::New(inner) => {
+                assert_pat!(&arena[inner].node, Expression::Invoke(_) => {});
+            }
+        );
+        assert_pat!(
+            Expression::from_str(&mut arena, "newline").unwrap(),
+            Expression::Name(name) => { assert_eq!(&name, "newline"); }
+        );
+    }
+
+    #[test]
+    fn record_literal() {
+        let mut arena = ExprArena::new();
+        let labels = |expr: &Expression| match expr {
+            Expression::Record(fields) => fields.iter().map(|(l, _)| l.to_string()).collect(),
+            _ => panic!("{:?}", expr),
+        };
+        let cases: &[(&str, &str, &[&str])] = &[
+            ("()", "()", &[]),
+            ("(1,)", "(1,)", &["0"]),
+            ("(1, 2,)", "(1, 2)", &["0", "1"]),
+            ("(x: 1)", "(x: 1,)", &["x"]),
+            ("(x: 1, y: 2 + 3)", "(x: 1, y: 2 + 3)", &["x", "y"]),
+            ("(1, y: (2,))", "(1, y: (2,))", &["0", "y"]),
+        ];
+        for (code, printed, expected) in cases {
+            let expr = Expression::from_str(&mut arena, code).unwrap();
+            assert_eq!(format!("{}", expr.display(&arena)), *printed);
+            let labels: Vec<String> = labels(&expr);
+            assert_eq!(labels, *expected);
+        }
+        assert_pat!(
+            Expression::from_str(&mut arena, "(1)").unwrap(),
+            Expression::Integral(_) => {}
+        );
+    }
+
+    #[test]
+    fn vec_literal() {
+        let mut arena = ExprArena::new();
+        let cases = &[
+            ("[]", "[]"),
+            ("[1]", "[1]"),
+            ("[1, 2 + 3,]", "[1, 2 + 3]"),
+            ("[[1], (x: 2,)]", "[[1], (x: 2,)]"),
+            ("v[0]", "v[0]"),
+            ("m[i][j + 1] len()", "m[i][j + 1] len()"),
+            ("f(1)[0].x", "f(1)[0].x"),
+        ];
+        for (code, printed) in cases {
+            let expr = Expression::from_str(&mut arena, code).unwrap();
+            assert_eq!(format!("{}", expr.display(&arena)), *printed);
+        }
+        assert_pat!(
+            Expression::from_str(&mut arena, "[a, b]").unwrap(),
+            Expression::Vec(items) => {
+                assert_eq!(items.len(), 2);
+            }
+        );
+    }
+
+    #[test]
+    fn field_access() {
+        let mut arena = ExprArena::new();
+        for code in &["p.x", "t.0.1", "(x: 1,).x", "p.x foo().y", "f(1).`+`"] {
+            let expr = Expression::from_str(&mut arena, code).unwrap();
+            assert_eq!(format!("{}", expr.display(&arena)), *code);
+        }
+        assert_pat!(
+            Expression::from_str(&mut arena, "a.b.c").unwrap(),
+            Expression::Field(inner, label) => {
+                assert_eq!(&label.node, "c");
+                assert_pat!(&arena[inner].node, Expression::Field(_, label) => {
+                    assert_eq!(&label.node, "b");
+                });
+            }
+        );
+    }
 }
@@ -4,6 +4,7 @@ pub mod call;
 pub mod control_flow;
 pub mod function;
 pub mod impls;
+pub mod switch;
 
 use crate::{
     find::Find,
@@ -14,8 +15,9 @@ use crate::{
 pub use self::{
     arena::{ExprArena, ExprIndex},
     call::{CallExpression, InvokeExpression},
-    control_flow::{IfExpression, LoopExpression},
+    control_flow::{ForExpression, IfExpression, LoopExpression},
     function::{FunctionExpression, Parameter},
+    switch::{Pattern, SwitchArm, SwitchExpression},
 };
 
 pub type BlockExpression = Spanned<block::BlockExpression>;
@@ -45,14 +47,36 @@ impl Find for Expression {
 
         match &self.node {
             // Atomic
-            False | True | Integral(_) | Str(_) | Name(_) => Some(span),
+            False | True | Integral(_) | Rational(_) | Str(_) | Name(_) => Some(span),
+            Format(parts) => parts
+                .iter()
+                .find_map(|part| match part {
+                    impls::FormatPart::Expr(expr) => expr.find_span(pos, arena),
+                    impls::FormatPart::Str(_) => None,
+                })
+                .or(Some(span)),
 
-            Record(_) => None,
+            Record(fields) => fields
+                .iter()
+                .find_map(|(_, value)| value.find_span(pos, arena))
+                .or(Some(span)),
+            Vec(items) => items
+                .iter()
+                .find_map(|item| item.find_span(pos, arena))
+                .or(Some(span)),
             Block(blk) => blk.find_span(pos, arena),
             Fn(expr) => expr.find_span(pos, arena),
 
             // Operator
             New(expr) => expr.find_span(pos, arena),
+            Field(expr, label) => label
+                .span
+                .find_span(pos, arena)
+                .or_else(|| expr.find_span(pos, arena)),
+            Index(expr, index) => expr
+                .find_span(pos, arena)
+                .or_else(|| index.find_span(pos, arena))
+                .or(Some(span)),
             Infix(op, left, right) => op
                 .span
                 .find_span(pos, arena)
@@ -64,6 +88,8 @@ impl Find for Expression {
             // Control flow
             If(expr) => expr.find_span(pos, arena),
             Loop(expr) => expr.find_span(pos, arena),
+            For(expr) => expr.find_span(pos, arena),
+            Switch(expr) => expr.find_span(pos, arena),
         }
     }
 }
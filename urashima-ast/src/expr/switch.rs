@@ -0,0 +1,354 @@
+use urashima_util::{
+    num::{parse_decimal, parse_integer, Decimal, Int, Rat},
+    Symbol,
+};
+
+#[cfg(feature = "deserialize")]
+use serde_derive::Deserialize;
+#[cfg(feature = "deserialize")]
+use serde_derive_state::DeserializeState;
+
+use super::{ExprArena, ExprIndex};
+use crate::{
+    error::{Error, Fallible},
+    find::Find,
+    parser::{name_of, string_of, Pair, Pairs, Parse, Rule},
+    print::{self, Print},
+    span::{Position, Span, Spanned},
+};
+
+/// `switch subject { pattern if guard => body ... }`
+#[derive(Clone)]
+#[cfg_attr(any(feature = "dev", test), derive(Debug))]
+#[cfg_attr(feature = "deserialize", derive(DeserializeState))]
+#[cfg_attr(feature = "deserialize", serde(deserialize_state = "ExprArena"))]
+pub struct SwitchExpression {
+    #[cfg_attr(feature = "deserialize", serde(skip))]
+    switch_keyword: Span,
+    #[cfg_attr(feature = "deserialize", serde(state))]
+    pub subject: Spanned<ExprIndex>,
+    #[cfg_attr(feature = "deserialize", serde(state))]
+    pub arms: Vec<SwitchArm>,
+}
+
+#[derive(Clone)]
+#[cfg_attr(any(feature = "dev", test), derive(Debug))]
+#[cfg_attr(feature = "deserialize", derive(DeserializeState))]
+#[cfg_attr(feature = "deserialize", serde(deserialize_state = "ExprArena"))]
+pub struct SwitchArm {
+    pub pattern: Spanned<Pattern>,
+    #[cfg_attr(feature = "deserialize", serde(default, state))]
+    pub guard: Option<ExprIndex>,
+    #[cfg_attr(feature = "deserialize", serde(state))]
+    pub body: ExprIndex,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "deserialize", derive(Deserialize))]
+pub enum Pattern {
+    /// `_` matches anything
+    Wildcard,
+    Bool(bool),
+    Integral(
+        #[cfg_attr(
+            feature = "deserialize",
+            serde(deserialize_with = "urashima_util::num::deserialize_int")
+        )]
+        Int,
+    ),
+    Rational(Rat),
+    Str(String),
+    /// A name matches anything, and binds the value to the name. If the name refers to a
+    /// variant declared without fields, only that variant matches.
+    Binding(Symbol),
+    /// Matches a record with the fields. Unless `rest` is set, the record should not have any
+    /// other fields. With a tag, like `Point(x, y)`, the record should be made by the
+    /// constructor of that name.
+    Record {
+        tag: Option<Symbol>,
+        fields: Vec<(Symbol, Spanned<Pattern>)>,
+        rest: bool,
+    },
+}
+
+impl Pattern {
+    /// Names bound by the pattern, in order of appearance
+    pub fn bindings(&self) -> Vec<&Symbol> {
+        let mut names = vec![];
+        self.collect_bindings(&mut names);
+        names
+    }
+
+    /// Constructor names of the tagged record patterns, in order of appearance
+    pub fn tags(&self) -> Vec<&Symbol> {
+        let mut names = vec![];
+        self.collect_tags(&mut names);
+        names
+    }
+
+    /// Names looked up in the scope when matching: the bound names, which may refer to
+    /// variants, followed by the tags
+    pub fn references(&self) -> Vec<&Symbol> {
+        let mut names = self.bindings();
+        names.extend(self.tags());
+        names
+    }
+
+    fn collect_tags<'a>(&'a self, names: &mut Vec<&'a Symbol>) {
+        if let Pattern::Record { tag, fields, .. } = self {
+            names.extend(tag);
+            for (_, pattern) in fields {
+                pattern.collect_tags(names);
+            }
+        }
+    }
+
+    fn collect_bindings<'a>(&'a self, names: &mut Vec<&'a Symbol>) {
+        match self {
+            Pattern::Binding(name) => names.push(name),
+            Pattern::Record { fields, .. } => {
+                for (_, pattern) in fields {
+                    pattern.collect_bindings(names);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Parse for SwitchExpression {
+    const RULE: Rule = Rule::switch_expression;
+
+    fn from_pairs<'i>(
+        arena: &mut ExprArena,
+        _span: pest::Span<'i>,
+        mut pairs: Pairs<'i>,
+    ) -> Fallible<Self> {
+        let switch_keyword = Span::from(&pairs.next().expect("unreachable").as_span());
+        let subject = Parse::from_pair(arena, pairs.next().expect("unreachable"))?;
+        let arms = pairs
+            .map(|pair| parse_switch_arm(arena, pair.into_inner()))
+            .collect::<Fallible<_>>()?;
+        Ok(SwitchExpression {
+            switch_keyword,
+            subject,
+            arms,
+        })
+    }
+}
+
+fn parse_switch_arm(arena: &mut ExprArena, mut pairs: Pairs<'_>) -> Fallible<SwitchArm> {
+    let pattern = parse_pattern(pairs.next().expect("unreachable"))?;
+    let mut next = pairs.next().expect("unreachable");
+    let guard = if next.as_rule() == Rule::switch_guard {
+        let expr = next.into_inner().nth(1).expect("unreachable");
+        next = pairs.next().expect("unreachable");
+        Some(ExprIndex::from_pair(&mut *arena, expr)?)
+    } else {
+        None
+    };
+    let body = ExprIndex::from_pair(arena, next)?;
+    Ok(SwitchArm {
+        pattern,
+        guard,
+        body,
+    })
+}
+
+fn parse_pattern(pair: Pair<'_>) -> Fallible<Spanned<Pattern>> {
+    let span = pair.as_span();
+    let pattern = match pair.as_rule() {
+        Rule::wildcard_pattern => Pattern::Wildcard,
+        Rule::binding_pattern => Pattern::Binding(name_of(&pair.into_inner().next().unwrap())),
+        Rule::literal_pattern => {
+            let literal = pair.into_inner().next().unwrap();
+            let text = literal.as_str();
+            match literal.as_rule() {
+                Rule::boolean => Pattern::Bool(text == "true"),
+                Rule::numeric if text.contains('.') => Pattern::Rational(
+                    parse_decimal(text).ok_or_else(|| Error::invalid_literal(text))?,
+                ),
+                Rule::numeric => Pattern::Integral(
+                    parse_integer(text).ok_or_else(|| Error::invalid_literal(text))?,
+                ),
+                _ => Pattern::Str(string_of(&literal)?),
+            }
+        }
+        Rule::record_pattern => parse_record_pattern(None, pair.into_inner())?,
+        Rule::tagged_pattern => {
+            let mut pairs = pair.into_inner();
+            let tag = name_of(&pairs.next().expect("unreachable"));
+            parse_record_pattern(Some(tag), pairs)?
+        }
+        _ => unreachable!(),
+    };
+    Ok(Spanned::new(&span, pattern))
+}
+
+fn parse_record_pattern(tag: Option<Symbol>, pairs: Pairs<'_>) -> Fallible<Pattern> {
+    let mut fields = vec![];
+    let mut rest = false;
+    let mut label = None;
+    for pair in pairs {
+        let (label, pattern) = match pair.as_rule() {
+            Rule::rest_pattern => {
+                rest = true;
+                continue;
+            }
+            Rule::name => {
+                label = Some(name_of(&pair));
+                continue;
+            }
+            Rule::record_pattern_field => {
+                let mut inner = pair.into_inner().collect::<Vec<_>>();
+                let pattern = parse_pattern(inner.pop().unwrap())?;
+                (inner.pop().map(|name| name_of(&name)), pattern)
+            }
+            _ => (label.take(), parse_pattern(pair)?),
+        };
+        let label = label.unwrap_or_else(|| fields.len().to_string().into());
+        if fields.iter().any(|(l, _)| *l == label) {
+            return Err(Error::duplicate_label(label));
+        }
+        fields.push((label, pattern));
+    }
+    Ok(Pattern::Record { tag, fields, rest })
+}
+
+impl Print for SwitchExpression {
+    fn fmt(&self, f: &mut print::Formatter<'_>) -> print::Result {
+        write!(f, "switch {} {{", f.display(&self.subject))?;
+        f.indent(|f| {
+            f.next_line()?;
+            for arm in &self.arms {
+                write!(f, "{}", arm.pattern.node)?;
+                if let Some(guard) = &arm.guard {
+                    write!(f, " if {}", f.display(guard))?;
+                }
+                write!(f, " => {}", f.display(&arm.body))?;
+                f.next_line()?;
+            }
+            Ok(())
+        })?;
+        f.write_str("}")
+    }
+}
+
+impl std::fmt::Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pattern::Wildcard => f.write_str("_"),
+            Pattern::Bool(b) => write!(f, "{}", b),
+            Pattern::Integral(i) => write!(f, "{}", i),
+            Pattern::Rational(r) => write!(f, "{}", Decimal(r)),
+            Pattern::Str(s) => write!(f, "{}", print::Str(s)),
+            Pattern::Binding(name) => write!(f, "{}", print::Name(name)),
+            Pattern::Record { tag, fields, rest } => {
+                if let Some(tag) = tag {
+                    write!(f, "{}", print::Name(tag))?;
+                }
+                f.write_str("(")?;
+                for (i, (label, pattern)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    if label.parse() != Ok(i) {
+                        write!(f, "{}: ", print::Name(label))?;
+                    }
+                    write!(f, "{}", pattern.node)?;
+                }
+                if *rest {
+                    if !fields.is_empty() {
+                        f.write_str(", ")?;
+                    }
+                    f.write_str("..")?;
+                } else if fields.len() == 1 && tag.is_none() {
+                    f.write_str(",")?;
+                }
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+impl Find for SwitchExpression {
+    fn find_span(&self, pos: Position, arena: &ExprArena) -> Option<Span> {
+        log::debug!("find_span(SwitchExpression)");
+        self.switch_keyword
+            .find_span(pos, arena)
+            .or_else(|| self.subject.find_span(pos, arena))
+            .or_else(|| {
+                self.arms.iter().find_map(|arm| {
+                    arm.pattern
+                        .find_span(pos, arena)
+                        .or_else(|| arm.guard.and_then(|g| g.find_span(pos, arena)))
+                        .or_else(|| arm.body.find_span(pos, arena))
+                })
+            })
+    }
+}
+
+impl Find for Spanned<Pattern> {
+    fn find_span(&self, pos: Position, arena: &ExprArena) -> Option<Span> {
+        let span = self.span.find_span(pos, arena)?;
+        match &self.node {
+            Pattern::Record { fields, .. } => fields
+                .iter()
+                .find_map(|(_, pattern)| pattern.find_span(pos, arena))
+                .or(Some(span)),
+            _ => Some(span),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::expr::{impls::Expression::*, Expression};
+
+    #[test]
+    fn switch_print() {
+        let code = r#"switch p {
+    0 => 'zero'
+    (x: 0, y) if y > 0 => y
+    (1, _, ..) => 'one'
+    (x: n,) => n
+    Point(x, ..) => x
+    Empty() => 0
+    'a\n' => 1.5
+    _ => 'other'
+}"#;
+        let mut arena = ExprArena::new();
+        let expr = Expression::from_str(&mut arena, code).unwrap();
+        assert_eq!(expr.display(&arena).to_string(), code);
+    }
+
+    #[test]
+    fn switch_arms() {
+        let mut arena = ExprArena::new();
+        assert_pat!(
+            Expression::from_str(&mut arena, "switch x { true => 1, (a, (b: c)) => 2 }").unwrap(),
+            Spanned { node: Switch(SwitchExpression { arms, .. }), .. } => {
+                assert_eq!(arms.len(), 2);
+                assert_pat!(&arms[0].pattern.node, Pattern::Bool(true) => {});
+                let names = arms[1].pattern.bindings();
+                assert_eq!(names.len(), 2);
+                assert_eq!(&**names[1], "c");
+            }
+        );
+        let err = Expression::from_str(&mut arena, "switch x { (a: 1, a: 2) => 0 }").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "All labels in the record should be unique: 'a' is used twice"
+        );
+
+        assert_pat!(
+            Expression::from_str(&mut arena, "switch t { Node(l, Leaf) => l }").unwrap(),
+            Spanned { node: Switch(SwitchExpression { arms, .. }), .. } => {
+                let names = arms[0].pattern.references();
+                assert_eq!(names, [&Symbol::from("l"), &"Leaf".into(), &"Node".into()]);
+                assert_eq!(arms[0].pattern.tags(), [&Symbol::from("Node")]);
+            }
+        );
+    }
+}
@@ -31,6 +31,6 @@ pub mod statement;
 
 pub use crate::{
     parser::{parse, Parse},
-    print::Print,
+    print::{Print, Str},
     find::Find,
 };
@@ -1,5 +1,6 @@
 use pest::Parser;
 use pest_derive::Parser;
+use urashima_util::Symbol;
 
 use crate::{
     error::{Error, Fallible},
@@ -65,6 +66,70 @@ pub(crate) fn ensure_single(mut pairs: Pairs<'_>) -> Pair<'_> {
     inner
 }
 
+/// Symbol of the given `name` pair, without quotes if it is a quoted name
+pub(crate) fn name_of(pair: &Pair<'_>) -> Symbol {
+    let s = pair.as_str();
+    if s.len() >= 2 && s.starts_with('`') && s.ends_with('`') {
+        s[1..s.len() - 1].into()
+    } else {
+        s.into()
+    }
+}
+
+/// Contents of the given string literal pair, with escape sequences processed unless it is raw.
+/// A multi-line string drops the line break right after its opening quotes, and the text
+/// between the holes of a format string has its doubled braces collapsed.
+pub(crate) fn string_of(pair: &Pair<'_>) -> Fallible<String> {
+    let s = pair.as_str();
+    match pair.as_rule() {
+        Rule::raw_string => Ok(s[2..s.len() - 1].to_string()),
+        Rule::multiline_string => {
+            let body = &s[3..s.len() - 3];
+            let body = body
+                .strip_prefix("\r\n")
+                .or_else(|| body.strip_prefix('\n'))
+                .unwrap_or(body);
+            unescape(body, false).ok_or_else(|| Error::invalid_literal(s))
+        }
+        Rule::format_text => unescape(s, true).ok_or_else(|| Error::invalid_literal(s)),
+        _ => unescape(&s[1..s.len() - 1], false).ok_or_else(|| Error::invalid_literal(s)),
+    }
+}
+
+/// The grammar only admits well-formed escapes, but `\u{...}` may still name
+/// something which is not a unicode scalar value.
+fn unescape(s: &str, braces: bool) -> Option<String> {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if braces && (c == '{' || c == '}') {
+            chars.next()?;
+            out.push(c);
+            continue;
+        }
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        let c = match chars.next()? {
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            '0' => '\0',
+            'u' => {
+                let rest = chars.as_str();
+                let end = rest.find('}')?;
+                let code = u32::from_str_radix(&rest[1..end], 16).ok()?;
+                chars = rest[end + 1..].chars();
+                char::from_u32(code)?
+            }
+            c => c,
+        };
+        out.push(c);
+    }
+    Some(out)
+}
+
 pub fn parse<T>(arena: &mut ExprArena, input: &str) -> Fallible<T>
 where
     T: Parse,
@@ -140,6 +140,61 @@ where
     }
 }
 
+/// Displays a name, quoting it if it is not an identifier
+pub(crate) struct Name<'a>(pub &'a str);
+
+impl fmt::Display for Name<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut chars = self.0.chars();
+        let is_identifier = chars
+            .next()
+            .is_some_and(|c| c == '_' || c.is_ascii_alphabetic())
+            && chars.all(|c| c == '_' || c.is_ascii_alphanumeric());
+        if is_identifier {
+            f.write_str(self.0)
+        } else {
+            write!(f, "`{}`", self.0)
+        }
+    }
+}
+
+/// Displays a string literal in single quotes, escaping it so that it parses back the same
+pub struct Str<'a>(pub &'a str);
+
+impl fmt::Display for Str<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_char('\'')?;
+        write_escaped(f, self.0, false)?;
+        f.write_char('\'')
+    }
+}
+
+/// Displays the text between the holes of a format string, escaped and with braces doubled
+pub(crate) struct FormatText<'a>(pub &'a str);
+
+impl fmt::Display for FormatText<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_escaped(f, self.0, true)
+    }
+}
+
+fn write_escaped(f: &mut fmt::Formatter<'_>, s: &str, braces: bool) -> fmt::Result {
+    for c in s.chars() {
+        match c {
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            '\0' => f.write_str("\\0")?,
+            '\\' => f.write_str("\\\\")?,
+            '\'' => f.write_str("\\'")?,
+            '{' | '}' if braces => write!(f, "{}{}", c, c)?,
+            c if c.is_control() => write!(f, "\\u{{{:x}}}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    Ok(())
+}
+
 pub(crate) struct Sequence<'a, 'b, T> {
     data: &'a [T],
     separator: &'b str,
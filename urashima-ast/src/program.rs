@@ -7,12 +7,12 @@ use serde_derive_state::DeserializeState;
 
 use crate::{
     error::Fallible,
-    expr::{ExprArena, Expression},
+    expr::{block::BlockExpression, impls, ExprArena, Expression},
     find::Find,
-    parser::{Pairs, Parse, Rule},
+    parser::{name_of, string_of, Pairs, Parse, Rule},
     print::{self, Print},
     span::{Position, Span, Spanned},
-    statement::Statement,
+    statement::{types::TypeDeclaration, Statement},
 };
 
 #[cfg_attr(any(feature = "dev", test), derive(Debug))]
@@ -28,6 +28,14 @@ pub struct PackageProgram {
     /// https://narucode.org/0/#Binding
     #[cfg_attr(feature = "deserialize", serde(state))]
     pub bindings: Vec<Binding>,
+
+    /// Type declarations, which are evaluated before the bindings
+    #[cfg_attr(feature = "deserialize", serde(default))]
+    pub types: Vec<TypeDeclaration>,
+
+    /// Tests, which are not run when the package is loaded
+    #[cfg_attr(feature = "deserialize", serde(default, state))]
+    pub tests: Vec<TestDeclaration>,
 }
 
 #[derive(Clone, PartialEq)]
@@ -50,12 +58,29 @@ pub struct Binding {
     pub value: Expression,
 }
 
+/// `test "name" { ... }`
+#[derive(Clone)]
+#[cfg_attr(any(feature = "dev", test), derive(Debug))]
+#[cfg_attr(feature = "deserialize", derive(DeserializeState))]
+#[cfg_attr(feature = "deserialize", serde(deserialize_state = "ExprArena"))]
+pub struct TestDeclaration {
+    #[cfg_attr(feature = "deserialize", serde(skip))]
+    test_keyword: Span,
+    pub name: Spanned<String>,
+    /// The block expression of the test body
+    #[cfg_attr(feature = "deserialize", serde(state))]
+    pub body: Expression,
+}
+
 #[cfg_attr(any(feature = "dev", test), derive(Debug))]
 #[cfg_attr(feature = "deserialize", derive(DeserializeState))]
 #[cfg_attr(feature = "deserialize", serde(deserialize_state = "ExprArena"))]
 pub struct ScriptProgram {
     #[cfg_attr(feature = "deserialize", serde(state))]
     pub statements: Vec<Statement>,
+    /// Tests declared in the script, which are not run when the script is executed
+    #[cfg_attr(feature = "deserialize", serde(default, state))]
+    pub tests: Vec<TestDeclaration>,
 }
 
 impl Parse for PackageProgram {
@@ -68,6 +93,8 @@ impl Parse for PackageProgram {
     ) -> Fallible<Self> {
         let mut uses = vec![];
         let mut bindings = vec![];
+        let mut types = vec![];
+        let mut tests = vec![];
         for item in pairs {
             match item.as_rule() {
                 Rule::use_declaration => {
@@ -84,12 +111,31 @@ impl Parse for PackageProgram {
                         item.into_inner(),
                     )?);
                 }
+                Rule::type_declaration => {
+                    types.push(TypeDeclaration::from_pairs(
+                        &mut *arena,
+                        item.as_span(),
+                        item.into_inner(),
+                    )?);
+                }
+                Rule::test_declaration => {
+                    tests.push(TestDeclaration::from_pairs(
+                        &mut *arena,
+                        item.as_span(),
+                        item.into_inner(),
+                    )?);
+                }
                 Rule::EOI => (),
                 _ => unreachable!(),
             }
         }
 
-        Ok(PackageProgram { uses, bindings })
+        Ok(PackageProgram {
+            uses,
+            bindings,
+            types,
+            tests,
+        })
     }
 }
 
@@ -109,14 +155,14 @@ impl Parse for PackageDep {
                     path = Some(
                         i.into_inner()
                             .map(|i| match i.as_rule() {
-                                Rule::name => i.as_str(),
+                                Rule::name => name_of(&i),
                                 _ => unreachable!(),
                             })
                             .collect(),
                     );
                 }
                 Rule::use_imports => imports.extend(i.into_inner().map(|i| match i.as_rule() {
-                    Rule::name => i.as_str().into(),
+                    Rule::name => name_of(&i),
                     _ => unreachable!(),
                 })),
                 _ => unreachable!(),
@@ -144,7 +190,7 @@ impl Parse for Binding {
             match i.as_rule() {
                 Rule::name => {
                     if name.is_none() {
-                        name = Some(Spanned::new(&i.as_span(), i.as_str().into()));
+                        name = Some(Spanned::new(&i.as_span(), name_of(&i)));
                     } else {
                         unreachable!();
                     }
@@ -178,6 +224,29 @@ impl Parse for Binding {
     }
 }
 
+impl Parse for TestDeclaration {
+    const RULE: Rule = Rule::test_declaration;
+
+    fn from_pairs<'i>(
+        arena: &mut ExprArena,
+        _span: pest::Span<'i>,
+        mut pairs: Pairs<'i>,
+    ) -> Fallible<Self> {
+        let test_keyword = Span::from(&pairs.next().expect("unreachable").as_span());
+        let name = pairs.next().expect("unreachable");
+        let name = Spanned::new(&name.as_span(), string_of(&name)?);
+        let body = pairs.next().expect("unreachable");
+        let span = body.as_span();
+        let blk = BlockExpression::from_pairs(arena, span.clone(), body.into_inner())?;
+        let blk = impls::Expression::Block(Spanned::new(&span, blk));
+        Ok(TestDeclaration {
+            test_keyword,
+            name,
+            body: Spanned::new(&span, blk),
+        })
+    }
+}
+
 impl Parse for ScriptProgram {
     const RULE: Rule = Rule::script_program;
 
@@ -187,6 +256,7 @@ impl Parse for ScriptProgram {
         pairs: Pairs<'i>,
     ) -> Fallible<Self> {
         let mut statements = vec![];
+        let mut tests = vec![];
         for item in pairs {
             match item.as_rule() {
                 Rule::statement => {
@@ -196,28 +266,56 @@ impl Parse for ScriptProgram {
                         item.into_inner(),
                     )?);
                 }
+                Rule::test_declaration => {
+                    tests.push(TestDeclaration::from_pairs(
+                        &mut *arena,
+                        item.as_span(),
+                        item.into_inner(),
+                    )?);
+                }
                 Rule::EOI => (),
                 _ => unreachable!(),
             }
         }
 
-        Ok(ScriptProgram { statements })
+        Ok(ScriptProgram { statements, tests })
     }
 }
 
 impl Print for Binding {
     fn fmt(&self, f: &mut print::Formatter<'_>) -> print::Result {
-        write!(f, "{} := ", self.name.node)?;
+        write!(f, "{} := ", print::Name(&self.name.node))?;
         Print::fmt(&self.value, f)
     }
 }
 
+impl Print for TestDeclaration {
+    fn fmt(&self, f: &mut print::Formatter<'_>) -> print::Result {
+        write!(f, "test {} ", print::Str(&self.name.node))?;
+        Print::fmt(&self.body, f)
+    }
+}
+
 impl Print for ScriptProgram {
     fn fmt(&self, f: &mut print::Formatter<'_>) -> print::Result {
+        // Put the tests back between the statements where they were declared.
+        let mut tests = self.tests.iter().peekable();
         for stmt in &self.statements {
+            while let Some(test) = tests.peek() {
+                if test.test_keyword.start() >= stmt.span.start() {
+                    break;
+                }
+                Print::fmt(*test, f)?;
+                f.next_line()?;
+                tests.next();
+            }
             Print::fmt(stmt, f)?;
             f.next_line()?;
         }
+        for test in tests {
+            Print::fmt(test, f)?;
+            f.next_line()?;
+        }
         Ok(())
     }
 }
@@ -232,14 +330,23 @@ impl Find for Binding {
     }
 }
 
+impl Find for TestDeclaration {
+    fn find_span(&self, pos: Position, arena: &ExprArena) -> Option<Span> {
+        log::debug!("find_span(TestDeclaration): {:?}", pos);
+        self.test_keyword
+            .find_span(pos, arena)
+            .or_else(|| self.name.span.find_span(pos, arena))
+            .or_else(|| self.body.find_span(pos, arena))
+    }
+}
+
 impl Find for ScriptProgram {
     fn find_span(&self, pos: Position, arena: &ExprArena) -> Option<Span> {
         log::debug!("find_span(ScriptProgram): {:?}", pos);
-        let i = self
-            .statements
-            .binary_search_by(|s| s.span.cmp_pos(&pos))
-            .ok()?;
-        self.statements[i].find_span(pos, arena)
+        match self.statements.binary_search_by(|s| s.span.cmp_pos(&pos)) {
+            Ok(i) => self.statements[i].find_span(pos, arena),
+            Err(_) => self.tests.iter().find_map(|t| t.find_span(pos, arena)),
+        }
     }
 }
 
@@ -294,4 +401,52 @@ baz := fn {
             }
         }
     }
+
+    #[test]
+    fn test_declarations() {
+        let mut arena = ExprArena::new();
+        let prog = PackageProgram::from_str(
+            &mut arena,
+            r#"
+double := fn (x) { x * 2 }
+
+test 'double a number' {
+    assert double(2) == 4
+}
+test "double zero" { assert double(0) == 0 }
+"#,
+        )
+        .unwrap();
+        assert_eq!(prog.bindings.len(), 1);
+        let names: Vec<_> = prog.tests.iter().map(|t| &t.name.node[..]).collect();
+        assert_eq!(names, ["double a number", "double zero"]);
+        assert_eq!(
+            prog.tests[1].display(&arena).to_string(),
+            "test 'double zero' {\n    assert double(0) == 0\n}"
+        );
+        assert!(PackageProgram::from_str(&mut arena, "test := 1\n").is_err());
+
+        let script = ScriptProgram::from_str(
+            &mut arena,
+            "double := fn (x) { x * 2 }\ntest 'double' { assert double(2) == 4 }\ndouble(1)\n",
+        )
+        .unwrap();
+        assert_eq!(script.statements.len(), 2);
+        assert_eq!(script.tests.len(), 1);
+        assert_eq!(
+            script.display(&arena).to_string(),
+            "double := fn(x) {\n    x * 2\n}\ntest 'double' {\n    assert double(2) == 4\n}\ndouble(1)\n"
+        );
+        assert!(ScriptProgram::from_str(&mut arena, "{ test 'nested' {} }").is_err());
+    }
+
+    #[test]
+    fn parse_error_span() {
+        let mut arena = ExprArena::new();
+        let err = ScriptProgram::from_str(&mut arena, "x := 1\ny := 2 +\n").unwrap_err();
+        let span = err.span().unwrap();
+        assert_eq!(span.start(), Position::new(2, 9));
+        assert_eq!(span.start().pos, Some(15));
+        assert_eq!(err.to_string(), "parse error: expected operand_expression");
+    }
 }
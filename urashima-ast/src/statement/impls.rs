@@ -1,13 +1,16 @@
 #[cfg(feature = "deserialize")]
 use serde_derive_state::DeserializeState;
 
+use urashima_util::Symbol;
+
 use crate::{
     error::Fallible,
-    expr::{ExprArena, Expression},
+    expr::{impls, ExprArena, ExprIndex, Expression},
     parser::{ensure_single, Pairs, Parse, Rule},
     print::{self, Print},
     program::{Binding, PackageDep},
-    span::Span,
+    span::{Span, Spanned},
+    statement::types::TypeDeclaration,
 };
 
 #[derive(Clone)]
@@ -18,6 +21,8 @@ pub enum Statement {
     Binding(#[cfg_attr(feature = "deserialize", serde(state))] Binding),
     Expr(#[cfg_attr(feature = "deserialize", serde(state))] Expression),
     Return(Span, #[cfg_attr(feature = "deserialize", serde(state))] Expression),
+    Assert(#[cfg_attr(feature = "deserialize", serde(state))] Assertion),
+    Type(TypeDeclaration),
     Break,
     Continue,
     Use(PackageDep),
@@ -33,6 +38,28 @@ impl Parse for Statement {
     ) -> Fallible<Self> {
         let item = ensure_single(pairs);
         match item.as_rule() {
+            Rule::assert_statement => {
+                let mut pairs = item.into_inner();
+                let keyword = Span::from(&pairs.next().expect("unreachable").as_span());
+                let cond = pairs.next().expect("unreachable");
+                let source = cond.as_str().trim_end().to_string();
+                let cond = Expression::from_pair(&mut *arena, cond)?;
+                let message = match pairs.next() {
+                    Some(message) => Some(ExprIndex::from_pair(&mut *arena, message)?),
+                    None => None,
+                };
+                Ok(Statement::Assert(Assertion {
+                    keyword,
+                    cond,
+                    message,
+                    source,
+                }))
+            }
+            Rule::type_declaration => {
+                let span = item.as_span();
+                let decl = TypeDeclaration::from_pairs(&mut *arena, span, item.into_inner())?;
+                Ok(Statement::Type(decl))
+            }
             Rule::break_statement => Ok(Statement::Break),
             Rule::continue_statement => Ok(Statement::Continue),
             Rule::return_statement => {
@@ -66,6 +93,14 @@ impl Print for Statement {
             Binding(b) => Print::fmt(b, f),
             Expr(expr) => Print::fmt(expr, f),
             Return(_, expr) => write!(f, "return {}", f.display(expr)),
+            Assert(a) => {
+                write!(f, "assert {}", f.display(&a.cond))?;
+                if let Some(message) = &a.message {
+                    write!(f, ", {}", f.display(message))?;
+                }
+                Ok(())
+            }
+            Type(decl) => Print::fmt(decl, f),
             Break => f.write_str("break"),
             Continue => f.write_str("continue"),
             Use(..) => unimplemented!(),
@@ -73,6 +108,37 @@ impl Print for Statement {
     }
 }
 
+/// `assert cond` or `assert cond, message`
+#[derive(Clone)]
+#[cfg_attr(any(feature = "dev", test), derive(Debug))]
+#[cfg_attr(feature = "deserialize", derive(DeserializeState))]
+#[cfg_attr(feature = "deserialize", serde(deserialize_state = "ExprArena"))]
+pub struct Assertion {
+    #[cfg_attr(feature = "deserialize", serde(skip))]
+    pub(crate) keyword: Span,
+    #[cfg_attr(feature = "deserialize", serde(state))]
+    pub cond: Expression,
+    #[cfg_attr(feature = "deserialize", serde(default, state))]
+    pub message: Option<ExprIndex>,
+    /// Source text of the condition, to be shown when the assertion fails
+    #[cfg_attr(feature = "deserialize", serde(default))]
+    pub source: String,
+}
+
+impl Assertion {
+    /// The operator and the operands of the condition, if it compares two values
+    pub fn comparison(&self) -> Option<(&Spanned<Symbol>, ExprIndex, ExprIndex)> {
+        match &self.cond.node {
+            impls::Expression::Infix(op, left, right)
+                if ["==", "!=", "<", "<=", ">", ">="].contains(&&*op.node) =>
+            {
+                Some((op, *left, *right))
+            }
+            _ => None,
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -113,7 +179,31 @@ mod test {
         let mut arena = ExprArena::new();
         assert_pat!(
             Statement::from_str(&mut arena, "return 42\n").unwrap(),
-            Statement::Return(_, Spanned { node: Expression::Integral(42), .. }) => { }
+            Statement::Return(_, Spanned { node: Expression::Integral(n), .. }) => { assert_eq!(n, 42.into()); }
+        );
+    }
+
+    #[test]
+    fn assert_comparison() {
+        let mut arena = ExprArena::new();
+        assert_pat!(
+            Statement::from_str(&mut arena, "assert x + 1 == 2, 'x should be one'\n").unwrap(),
+            Statement::Assert(a) => {
+                assert_eq!(a.source, "x + 1 == 2");
+                assert_pat!(a.comparison(), Some((op, _, _)) => { assert_eq!(&op.node, "=="); });
+                assert_pat!(a.message.map(|m| &arena[m].node), Some(Expression::Str(_)) => {});
+            }
+        );
+        assert_pat!(
+            Statement::from_str(&mut arena, "assert ok").unwrap(),
+            Statement::Assert(a) => {
+                assert!(a.comparison().is_none());
+                assert!(a.message.is_none());
+            }
+        );
+        assert_pat!(
+            Statement::from_str(&mut arena, "assert x == 2   , 'm'").unwrap(),
+            Statement::Assert(a) => { assert_eq!(a.source, "x == 2"); }
         );
     }
 
@@ -122,8 +212,9 @@ mod test {
         let mut arena = ExprArena::new();
         assert_pat!(
             Statement::from_str(&mut arena, "foo := 42\n").unwrap(),
-            Statement::Binding(Binding { name, value: Spanned { node: Expression::Integral(42), .. }, .. }) => {
+            Statement::Binding(Binding { name, value: Spanned { node: Expression::Integral(n), .. }, .. }) => {
                 assert_eq!(&name.node, "foo");
+                assert_eq!(n, 42.into());
             }
         );
     }
@@ -1,4 +1,5 @@
 pub mod impls;
+pub mod types;
 
 use crate::{
     expr::ExprArena,
@@ -19,6 +20,12 @@ impl Find for Statement {
             Binding(b) => b.find_span(pos, arena),
             Expr(expr) => expr.find_span(pos, arena),
             Return(keyword, expr) => keyword.find_span(pos, arena).or_else(|| expr.find_span(pos, arena)),
+            Assert(a) => a
+                .keyword
+                .find_span(pos, arena)
+                .or_else(|| a.cond.find_span(pos, arena))
+                .or_else(|| a.message.and_then(|m| m.find_span(pos, arena))),
+            Type(decl) => decl.find_span(pos, arena),
             Break | Continue => Some(span),
             Use(pkg) => None,
         }
@@ -0,0 +1,220 @@
+use urashima_util::Symbol;
+
+#[cfg(feature = "deserialize")]
+use serde_derive::Deserialize;
+
+use crate::{
+    error::{Error, Fallible},
+    expr::ExprArena,
+    find::Find,
+    parser::{name_of, Pair, Pairs, Parse, Rule},
+    print::{self, Print},
+    span::{Position, Span, Spanned},
+};
+
+/// `type Name := (label: type, ...)`, or a tagged union `type Name := Variant(...) | ...`
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "deserialize", derive(Deserialize))]
+pub struct TypeDeclaration {
+    #[cfg_attr(feature = "deserialize", serde(skip))]
+    type_keyword: Span,
+    pub name: Spanned<Symbol>,
+    pub definition: TypeDefinition,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "deserialize", derive(Deserialize))]
+pub enum TypeDefinition {
+    /// A record type, whose constructor has the name of the type
+    Record(Vec<FieldDeclaration>),
+    /// A tagged union, which has a constructor for each variant
+    Union(Vec<VariantDeclaration>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "deserialize", derive(Deserialize))]
+pub struct VariantDeclaration {
+    pub name: Spanned<Symbol>,
+    /// `None` if the variant is declared without parentheses, which makes it a value
+    /// rather than a constructor
+    pub fields: Option<Vec<FieldDeclaration>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "deserialize", derive(Deserialize))]
+pub struct FieldDeclaration {
+    /// Positional fields are labelled with their positions
+    pub label: Symbol,
+    pub ty: Spanned<Symbol>,
+}
+
+impl TypeDeclaration {
+    /// Names bound by the declaration: the type itself for a record type, or the variants
+    pub fn bindings(&self) -> Vec<&Symbol> {
+        match &self.definition {
+            TypeDefinition::Record(_) => vec![&self.name.node],
+            TypeDefinition::Union(variants) => variants.iter().map(|v| &v.name.node).collect(),
+        }
+    }
+}
+
+impl Parse for TypeDeclaration {
+    const RULE: Rule = Rule::type_declaration;
+
+    fn from_pairs<'i>(
+        _arena: &mut ExprArena,
+        _span: pest::Span<'i>,
+        mut pairs: Pairs<'i>,
+    ) -> Fallible<Self> {
+        let type_keyword = Span::from(&pairs.next().expect("unreachable").as_span());
+        let name = pairs.next().expect("unreachable");
+        let name = Spanned::new(&name.as_span(), name_of(&name));
+        pairs.next().expect("unreachable"); // OPERATOR_BIND
+        let first = pairs.next().expect("unreachable");
+        let definition = if first.as_rule() == Rule::type_fields {
+            TypeDefinition::Record(parse_fields(first)?)
+        } else {
+            let mut variants: Vec<VariantDeclaration> = vec![];
+            for pair in std::iter::once(first).chain(pairs) {
+                let span = pair.as_span();
+                let mut inner = pair.into_inner();
+                let name = inner.next().expect("unreachable");
+                let name = Spanned::new(&name.as_span(), name_of(&name));
+                if variants.iter().any(|v| v.name.node == name.node) {
+                    return Err(Error::invalid_literal(span.as_str()));
+                }
+                let fields = inner.next().map(parse_fields).transpose()?;
+                variants.push(VariantDeclaration { name, fields });
+            }
+            TypeDefinition::Union(variants)
+        };
+        Ok(TypeDeclaration {
+            type_keyword,
+            name,
+            definition,
+        })
+    }
+}
+
+fn parse_fields(pair: Pair<'_>) -> Fallible<Vec<FieldDeclaration>> {
+    let mut fields: Vec<FieldDeclaration> = vec![];
+    for field in pair.into_inner() {
+        let span = field.as_span();
+        let mut names: Vec<_> = field.into_inner().collect();
+        let ty = names.pop().expect("unreachable");
+        let ty = Spanned::new(&ty.as_span(), name_of(&ty));
+        let label = match names.pop() {
+            Some(label) => name_of(&label),
+            None => fields.len().to_string().into(),
+        };
+        if fields.iter().any(|f| f.label == label) {
+            return Err(Error::invalid_literal(span.as_str()));
+        }
+        fields.push(FieldDeclaration { label, ty });
+    }
+    Ok(fields)
+}
+
+impl Print for TypeDeclaration {
+    fn fmt(&self, f: &mut print::Formatter<'_>) -> print::Result {
+        write!(f, "type {} := ", print::Name(&self.name.node))?;
+        match &self.definition {
+            TypeDefinition::Record(fields) => write!(f, "{}", Fields(fields)),
+            TypeDefinition::Union(variants) => {
+                for (i, variant) in variants.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" | ")?;
+                    }
+                    write!(f, "{}", print::Name(&variant.name.node))?;
+                    if let Some(fields) = &variant.fields {
+                        write!(f, "{}", Fields(fields))?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+struct Fields<'a>(&'a [FieldDeclaration]);
+
+impl std::fmt::Display for Fields<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("(")?;
+        for (i, field) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            if field.label.parse() != Ok(i) {
+                write!(f, "{}: ", print::Name(&field.label))?;
+            }
+            write!(f, "{}", print::Name(&field.ty.node))?;
+        }
+        f.write_str(")")
+    }
+}
+
+impl Find for TypeDeclaration {
+    fn find_span(&self, pos: Position, arena: &ExprArena) -> Option<Span> {
+        log::debug!("find_span(TypeDeclaration): {:?}", pos);
+        let fields = |fields: &[FieldDeclaration]| {
+            fields.iter().find_map(|f| f.ty.span.find_span(pos, arena))
+        };
+        self.type_keyword
+            .find_span(pos, arena)
+            .or_else(|| self.name.span.find_span(pos, arena))
+            .or_else(|| match &self.definition {
+                TypeDefinition::Record(f) => fields(f),
+                TypeDefinition::Union(variants) => variants.iter().find_map(|v| {
+                    v.name
+                        .span
+                        .find_span(pos, arena)
+                        .or_else(|| v.fields.as_deref().and_then(fields))
+                }),
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{program::PackageProgram, statement::impls::Statement};
+
+    #[test]
+    fn type_record() {
+        let mut arena = ExprArena::new();
+        let stmt = Statement::from_str(&mut arena, "type Point := (x: int, y: int)\n").unwrap();
+        assert_pat!(stmt, Statement::Type(decl) => {
+            assert_eq!(&decl.name.node, "Point");
+            assert_pat!(&decl.definition, TypeDefinition::Record(fields) => {
+                let labels: Vec<_> = fields.iter().map(|f| &*f.label).collect();
+                assert_eq!(labels, ["x", "y"]);
+                assert_eq!(&fields[1].ty.node, "int");
+            });
+        });
+        let stmt = Statement::from_str(&mut arena, "type Pair := (int, str)").unwrap();
+        assert_eq!(stmt.display(&arena).to_string(), "type Pair := (int, str)");
+        assert!(Statement::from_str(&mut arena, "type P := (x: int, x: int)").is_err());
+    }
+
+    #[test]
+    fn type_union() {
+        let mut arena = ExprArena::new();
+        let code = "type Shape := Circle(r: rat) | Rect(w: rat, h: rat) | Empty";
+        let stmt = Statement::from_str(&mut arena, code).unwrap();
+        assert_eq!(stmt.display(&arena).to_string(), code);
+        assert_pat!(stmt, Statement::Type(TypeDeclaration { definition: TypeDefinition::Union(variants), .. }) => {
+            assert_eq!(variants.len(), 3);
+            assert!(variants[2].fields.is_none());
+        });
+        assert!(Statement::from_str(&mut arena, "type T := A | A").is_err());
+
+        let prog = PackageProgram::from_str(
+            &mut arena,
+            "type Tree := Leaf\n    | Node(left: Tree, right: Tree)\nroot := Leaf\n",
+        )
+        .unwrap();
+        assert_eq!(prog.types.len(), 1);
+        assert_eq!(prog.bindings.len(), 1);
+    }
+}
@@ -222,12 +222,16 @@ impl_notifications! {
 
     "textDocument/didOpen"(self, params) {
         let doc = params.text_document;
-        self.apply_file_changes(doc.uri, Some(doc.version), vec![ TextDocumentContentChangeEvent { text: doc.text, range: None, range_length: None }]);
+        if let Err(e) = self.apply_file_changes(doc.uri, Some(doc.version), vec![ TextDocumentContentChangeEvent { text: doc.text, range: None, range_length: None }]) {
+            self.log_message(lsp_types::MessageType::Warning, e).await;
+        }
     }
 
     "textDocument/didChange"(self, params) {
         let doc = params.text_document;
-        self.apply_file_changes(doc.uri, doc.version, params.content_changes);
+        if let Err(e) = self.apply_file_changes(doc.uri, doc.version, params.content_changes) {
+            self.log_message(lsp_types::MessageType::Warning, e).await;
+        }
     }
 }
 
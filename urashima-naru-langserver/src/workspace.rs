@@ -170,12 +170,13 @@ impl WorkspaceState {
         mailbox.log_message(typ, msg).await;
     }
 
+    /// Update the source and parse it again. Parse errors are rendered with the source lines.
     pub fn apply_file_changes(
         &self,
         uri: Url,
         version: Option<u64>,
         changes: Vec<TextDocumentContentChangeEvent>,
-    ) -> urashima::Fallible<()> {
+    ) -> Result<(), String> {
         let mut result = Ok(());
         self.sources.alter(uri.clone(), |entry| {
             let mut entry = if let Some(mut e) = entry {
@@ -203,7 +204,8 @@ impl WorkspaceState {
                     entry.ast = Some(ast);
                 }
                 Err(e) => {
-                    result = Err(e.into());
+                    let e = urashima::Error::from(e);
+                    result = Err(e.report(&entry.text).to_string());
                 }
             }
             Some(entry)
@@ -8,7 +8,8 @@ const ATOMS: &[&str] = &[
     "assert", "break", "continue", "else", "false", "fn", "for", "if", "new", "package", "pub",
     "return", "static", "switch", "syntax", "test", "true", "type", "use", "var", "yield",
     // stdlib
-    "naru", "core", "bool", "int", "nat", "rat", "str", "vec", "ref", "result", "println",
+    "naru", "core", "any", "bool", "int", "nat", "rat", "str", "vec", "ref", "range", "result",
+    "println",
 ];
 
 fn main() {
@@ -1,10 +1,13 @@
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt;
+use core::hash::{Hash, Hasher};
 use core::marker::PhantomData;
 use core::ops;
 
 #[derive(Clone, Debug)]
 pub struct Arena<T>(generational_arena::Arena<T>);
 
-#[derive(Debug, Hash, Eq, Ord, PartialEq, PartialOrd)]
 pub struct Index<T>(generational_arena::Index, PhantomData<T>);
 
 impl<T> Clone for Index<T> {
@@ -15,6 +18,38 @@ impl<T> Clone for Index<T> {
 
 impl<T> Copy for Index<T> {}
 
+impl<T> fmt::Debug for Index<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Index").field(&self.0).finish()
+    }
+}
+
+impl<T> PartialEq for Index<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<T> Eq for Index<T> {}
+
+impl<T> PartialOrd for Index<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Index<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.cmp(&other.0)
+    }
+}
+
+impl<T> Hash for Index<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
 #[allow(dead_code)]
 impl<T> Arena<T> {
     pub fn new() -> Self {
@@ -40,6 +75,20 @@ impl<T> Arena<T> {
     pub fn get_mut(&mut self, i: Index<T>) -> Option<&mut T> {
         self.0.get_mut(i.0)
     }
+
+    /// Remove the values for which the predicate returns false, and returns how many are removed.
+    pub fn retain(&mut self, mut predicate: impl FnMut(Index<T>, &T) -> bool) -> usize {
+        let removed: Vec<_> = self
+            .0
+            .iter()
+            .filter(|(i, value)| !predicate(Index::from_raw(*i), value))
+            .map(|(i, _)| i)
+            .collect();
+        for &i in &removed {
+            self.0.remove(i);
+        }
+        removed.len()
+    }
 }
 
 impl<T> Default for Arena<T> {
@@ -88,6 +137,19 @@ mod test {
 
     use super::*;
 
+    #[test]
+    fn retain() {
+        let mut arena = Arena::new();
+        let indices: Vec<_> = (0..10).map(|i| arena.insert(i)).collect();
+        assert_eq!(arena.retain(|_, &v| v % 3 == 0), 6);
+        assert_eq!(arena.len(), 4);
+        assert_eq!(arena.get(indices[3]), Some(&3));
+        assert_eq!(arena.get(indices[4]), None);
+        let i = arena.insert(42);
+        assert_eq!(arena.get(i), Some(&42));
+        assert_eq!(arena.get(indices[4]), None);
+    }
+
     #[test]
     fn index_size() {
         assert!(mem::size_of::<Index<()>>() <= 16);
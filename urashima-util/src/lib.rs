@@ -17,6 +17,8 @@
 #![cfg_attr(test, recursion_limit = "128")]
 #![no_std]
 
+extern crate alloc;
+
 #[macro_use]
 pub mod symbol;
 
@@ -1,2 +1,155 @@
+use core::fmt;
+use core::str::FromStr;
+
 pub use num_bigint::{BigInt as Int, BigUint as Nat, ToBigInt as ToInt, ToBigUint as ToNat};
-pub use num_traits::Signed;
+pub use num_rational::BigRational as Rat;
+pub use num_traits::{Num, One, Signed, ToPrimitive, Zero};
+
+/// Parses an integer literal, which may have a `0x`, `0o` or `0b` prefix and `_` separators.
+pub fn parse_integer(s: &str) -> Option<Int> {
+    let (radix, digits) = match s.get(..2) {
+        Some("0x") => (16, &s[2..]),
+        Some("0o") => (8, &s[2..]),
+        Some("0b") => (2, &s[2..]),
+        _ => (10, s),
+    };
+    if !digits
+        .bytes()
+        .all(|b| b == b'_' || b.is_ascii_alphanumeric())
+    {
+        return None;
+    }
+    Int::from_str_radix(digits, radix).ok()
+}
+
+/// Parses a decimal literal like `12.345` into an exact rational number.
+pub fn parse_decimal(s: &str) -> Option<Rat> {
+    let (int, frac) = match s.find('.') {
+        Some(i) => (&s[..i], &s[i + 1..]),
+        None => (s, ""),
+    };
+    let is_digits = |s: &str| s.bytes().all(|b| b == b'_' || b.is_ascii_digit());
+    if int.is_empty() || !is_digits(int) || !is_digits(frac) {
+        return None;
+    }
+    let numer = Int::from_str(int).ok()?;
+    let mut denom = Int::one();
+    let mut frac_numer = Int::zero();
+    for d in frac.bytes().filter(|&b| b != b'_') {
+        frac_numer = frac_numer * 10u32 + u32::from(d - b'0');
+        denom *= 10u32;
+    }
+    Some(Rat::new(numer * &denom + frac_numer, denom))
+}
+
+/// Displays a rational number as a decimal if it can be written with finite digits,
+/// or as a fraction like `1/3` otherwise.
+pub struct Decimal<'a>(pub &'a Rat);
+
+impl fmt::Display for Decimal<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let numer = self.0.numer();
+        let denom = self.0.denom();
+        let mut rest = denom.clone();
+        let mut scale = 1usize;
+        for p in &[2u32, 5] {
+            let mut count = 0;
+            while (&rest % *p).is_zero() {
+                rest /= *p;
+                count += 1;
+            }
+            scale = scale.max(count);
+        }
+        if !rest.is_one() {
+            return write!(f, "{}/{}", numer, denom);
+        }
+        let unit = num_traits::pow(Int::from(10u32), scale);
+        let scaled = numer.abs() * &unit / denom;
+        let sign = if numer.is_negative() { "-" } else { "" };
+        write!(
+            f,
+            "{}{}.{:0width$}",
+            sign,
+            &scaled / &unit,
+            &scaled % &unit,
+            width = scale
+        )
+    }
+}
+
+#[cfg(feature = "deserialize")]
+pub fn deserialize_int<'de, D>(deserializer: D) -> Result<Int, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    struct V;
+    impl<'a> serde::de::Visitor<'a> for V {
+        type Value = Int;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("an integer or a string of an integer literal")
+        }
+
+        fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E> {
+            Ok(v.into())
+        }
+
+        fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E> {
+            Ok(v.into())
+        }
+
+        fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+        where
+            E: serde::de::Error,
+        {
+            let (negative, digits) = match v.strip_prefix('-') {
+                Some(digits) => (true, digits),
+                None => (false, v),
+            };
+            let n = parse_integer(digits).ok_or_else(|| E::custom("invalid integer literal"))?;
+            Ok(if negative { -n } else { n })
+        }
+    }
+    deserializer.deserialize_any(V)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    extern crate std;
+    use std::string::ToString;
+
+    #[test]
+    fn integer_literal() {
+        assert_eq!(parse_integer("42"), Some(Int::from(42)));
+        assert_eq!(parse_integer("1_000_000"), Some(Int::from(1_000_000)));
+        assert_eq!(parse_integer("0xff_ff"), Some(Int::from(0xffff)));
+        assert_eq!(parse_integer("0o755"), Some(Int::from(0o755)));
+        assert_eq!(parse_integer("0b1010"), Some(Int::from(10)));
+        assert_eq!(
+            parse_integer("18446744073709551616"),
+            Some(Int::from(u64::MAX) + 1)
+        );
+        assert_eq!(parse_integer("0x"), None);
+        assert_eq!(parse_integer("0b12"), None);
+    }
+
+    #[test]
+    fn decimal_roundtrip() {
+        for s in &["0.5", "1.0", "12.345", "0.001", "100.25"] {
+            let r = parse_decimal(s).unwrap();
+            assert_eq!(Decimal(&r).to_string(), *s);
+        }
+    }
+
+    #[test]
+    fn decimal_display() {
+        let r = Rat::new(Int::from(1), Int::from(3));
+        assert_eq!(Decimal(&r).to_string(), "1/3");
+        let r = Rat::new(Int::from(-3), Int::from(4));
+        assert_eq!(Decimal(&r).to_string(), "-0.75");
+        let r = Rat::from_integer(Int::from(-2));
+        assert_eq!(Decimal(&r).to_string(), "-2.0");
+    }
+}
@@ -18,6 +18,15 @@ impl<'a> FromIterator<&'a str> for PackagePath {
     }
 }
 
+impl FromIterator<Symbol> for PackagePath {
+    fn from_iter<T>(iter: T) -> Self
+    where
+        T: IntoIterator<Item = Symbol>,
+    {
+        PackagePath(iter.into_iter().collect())
+    }
+}
+
 impl<'a> IntoIterator for &'a PackagePath {
     type Item = &'a Symbol;
     type IntoIter = slice::Iter<'a, Symbol>;